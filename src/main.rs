@@ -6,16 +6,83 @@ use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::Path;
 use wiki_extractor::create_ja_config;
+use wiki_extractor::output::output_bulk::OutputBulk;
+use wiki_extractor::output::output_chunk::{ChunkSettings, ChunkUnit, OutputChunk};
+use wiki_extractor::output::output_geojson::OutputGeoJson;
+use wiki_extractor::output::output_http::{HttpSettings, OutputHttp};
 use wiki_extractor::output::output_json::OutputJson;
+use wiki_extractor::output::output_markdown::OutputMarkdown;
+use wiki_extractor::output::output_sqlite::OutputSqlite;
+use wiki_extractor::output::output_text::{OutputText, TextSettings};
+use wiki_extractor::output::output_wikiextractor::OutputWikiExtractor;
+use wiki_extractor::output::Output;
+use wiki_extractor::parser::annotation_parser::{
+    push_end_tag, push_footnotes, push_link_text, push_tag, push_template_text, replace_markers,
+    strip_annotations, BOLD, FOOTNOTE, ITALIC,
+};
+use wiki_extractor::parser::authority_parser::parse_authority_control;
 use wiki_extractor::parser::common_parser::{
     extract_external_link_text, extract_heading_text, extract_image, extract_link_text,
 };
+use wiki_extractor::parser::coordinate_parser::parse_coordinates;
+use wiki_extractor::parser::date_parser::parse_dates;
+use wiki_extractor::parser::era_parser::parse_eras;
+use wiki_extractor::parser::identifier_parser::parse_identifiers;
+use wiki_extractor::parser::infobox_parser::parse_infobox;
 use wiki_extractor::parser::list_parser::{parse_definition_items, parse_items, parse_order_items};
-use wiki_extractor::parser::model::Document;
-use wiki_extractor::parser::template_parser::parse_template;
-use wiki_extractor::wiki_page_iterator::WikiPageIterator;
+use wiki_extractor::parser::markdown_parser::parse_markdown;
+use wiki_extractor::parser::model::{AnnotationType, Document};
+use wiki_extractor::parser::parser_function::DateTime;
+use wiki_extractor::parser::passage_parser::{
+    parse_abstract, parse_sections, split_document, AbstractSettings, Granularity, SectionInfo,
+};
+use wiki_extractor::parser::provenance_parser::{node_range, parse_provenance, Segment};
+use wiki_extractor::parser::reading_parser::parse_readings;
+use wiki_extractor::parser::reference_parser::{nested_refs, parse_references};
+use wiki_extractor::parser::table_parser::{linearize_table, parse_table, TableFormat};
+use wiki_extractor::parser::template_expander::{ExpandSettings, SourceMap, TemplateExpander};
+use wiki_extractor::parser::template_parser::{
+    parse_template_rule, TemplateContext, TemplateRule, TemplateRules,
+};
+use wiki_extractor::wiki_page_iterator::{Page, WikiPageIterator};
 
-fn parse_config(args: &[String]) -> (&str, &str) {
+struct Config<'a> {
+    input_file: &'a str,
+    output_prefix: &'a str,
+    format: &'a str,
+    index: &'a str,
+    url: &'a str,
+    concurrency: usize,
+    batch_size: usize,
+    max_retries: u32,
+    bytes: u64,
+    text: TextSettings,
+    markdown: bool,
+    granularity: Granularity,
+    chunk: ChunkSettings,
+    abstract_settings: AbstractSettings,
+    annotations: Vec<AnnotationType>,
+    provenance: bool,
+    table_format: Option<TableFormat>,
+    template_rules: Option<&'a str>,
+    expand_templates: bool,
+    expand: ExpandSettings,
+    geojson: bool,
+}
+
+// wiki-extractor <input_file> <output_prefix>
+//   [--format json|sqlite|bulk|http|wikiextractor|wikiextractor-json|text|markdown|chunk]
+//   [--index name]
+//   [--url http://localhost:9200/_bulk] [--concurrency 4] [--batch-size 500] [--max-retries 5]
+//   [--bytes 1M] [--sentence-per-line] [--with-headings] [--without-lists] [--with-header]
+//   [--skip-sections 脚注,参考文献,...] [--markdown] [--granularity page|section|paragraph]
+//   [--chunk-size 512] [--chunk-overlap 64] [--chunk-unit char|token]
+//   [--abstract-paragraph] [--abstract-sentences N]
+//   [--annotations link,external_link,bold,italic,lang,era,ruby] [--provenance]
+//   [--table-format tsv|markdown] [--template-rules rules.json]
+//   [--expand-templates] [--expand-max-depth 20] [--expand-max-size 2M]
+//   [--geojson]
+fn parse_config(args: &[String]) -> Config<'_> {
     let input_file = &args[1];
     let output_prefix = &args[2];
     let input_path = Path::new(input_file);
@@ -23,7 +90,195 @@ fn parse_config(args: &[String]) -> (&str, &str) {
         warn!("First argument should be file path.");
         panic!("First argument should be file path.");
     }
-    (input_file, output_prefix)
+    let mut config = Config {
+        input_file,
+        output_prefix,
+        format: "json",
+        index: "jawiki",
+        url: "http://localhost:9200/_bulk",
+        concurrency: 4,
+        batch_size: 500,
+        max_retries: 5,
+        bytes: 1024 * 1024,
+        text: TextSettings::default(),
+        markdown: false,
+        granularity: Granularity::Page,
+        chunk: ChunkSettings {
+            size: 512,
+            overlap: 64,
+            unit: ChunkUnit::Char,
+        },
+        abstract_settings: AbstractSettings {
+            first_paragraph: false,
+            sentences: 0,
+        },
+        annotations: vec![],
+        provenance: false,
+        table_format: None,
+        template_rules: None,
+        expand_templates: false,
+        expand: ExpandSettings::default(),
+        geojson: false,
+    };
+    // set after the options, the default depends on the format
+    let mut granularity = None;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .map(|value| value.as_str())
+                .unwrap_or_else(|| panic!("Option [{}] needs a value.", option))
+        };
+        match option.as_str() {
+            "--format" => config.format = value(),
+            "--index" => config.index = value(),
+            "--url" => config.url = value(),
+            "--concurrency" => config.concurrency = parse_number(option, value()),
+            "--batch-size" => config.batch_size = parse_number(option, value()),
+            "--max-retries" => config.max_retries = parse_number(option, value()),
+            "--bytes" => config.bytes = parse_size(option, value()),
+            "--sentence-per-line" => config.text.sentence_per_line = true,
+            "--with-headings" => config.text.headings = true,
+            "--without-lists" => config.text.lists = false,
+            "--with-header" => config.text.header = true,
+            "--markdown" => config.markdown = true,
+            "--provenance" => config.provenance = true,
+            "--geojson" => config.geojson = true,
+            "--chunk-size" => config.chunk.size = parse_number(option, value()),
+            "--chunk-overlap" => config.chunk.overlap = parse_number(option, value()),
+            "--chunk-unit" => {
+                let value = value();
+                config.chunk.unit = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Unknown chunk unit [{}].", value))
+            }
+            "--abstract-paragraph" => config.abstract_settings.first_paragraph = true,
+            "--abstract-sentences" => {
+                config.abstract_settings.sentences = parse_number(option, value())
+            }
+            "--template-rules" => config.template_rules = Some(value()),
+            "--expand-templates" => config.expand_templates = true,
+            "--expand-max-depth" => config.expand.max_depth = parse_number(option, value()),
+            "--expand-max-size" => config.expand.max_size = parse_size(option, value()) as usize,
+            "--table-format" => {
+                let value = value();
+                config.table_format = Some(
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("Unknown table format [{}].", value)),
+                )
+            }
+            "--granularity" => {
+                let value = value();
+                granularity = Some(
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("Unknown granularity [{}].", value)),
+                )
+            }
+            "--annotations" => {
+                config.annotations = value()
+                    .split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| {
+                        s.parse()
+                            .unwrap_or_else(|_| panic!("Unknown annotation type [{}].", s))
+                    })
+                    .collect()
+            }
+            "--skip-sections" => {
+                config.text.skip_sections = value()
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            }
+            _ => {
+                warn!("Unknown option [{}].", option);
+                panic!("Unknown option [{}].", option);
+            }
+        }
+    }
+    config.granularity = match (config.format, granularity) {
+        // markdown is rendered per page, sections and paragraphs have none
+        ("markdown", Some(Granularity::Section | Granularity::Paragraph)) => {
+            warn!("--format markdown can't be used with --granularity section|paragraph.");
+            panic!("--format markdown can't be used with --granularity section|paragraph.");
+        }
+        // chunks are made from section records and never cross sections
+        ("chunk", None | Some(Granularity::Section)) => Granularity::Section,
+        ("chunk", Some(_)) => {
+            warn!("--format chunk can only be used with --granularity section.");
+            panic!("--format chunk can only be used with --granularity section.");
+        }
+        (_, granularity) => granularity.unwrap_or(Granularity::Page),
+    };
+    if config.format == "markdown" {
+        config.markdown = true;
+    }
+    if config.format == "chunk" && config.chunk.size == 0 {
+        warn!("--chunk-size should be larger than 0.");
+        panic!("--chunk-size should be larger than 0.");
+    }
+    config
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| panic!("Option [{}] should be a number. [{}]", option, value))
+}
+
+// 500K, 1M, 1G or plain bytes
+fn parse_size(option: &str, value: &str) -> u64 {
+    let (number, unit) = match value.chars().last() {
+        Some('K') | Some('k') => (&value[..value.len() - 1], 1024),
+        Some('M') | Some('m') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('G') | Some('g') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    parse_number::<u64>(option, number) * unit
+}
+
+fn create_output(config: &Config) -> Box<dyn Output> {
+    match config.format {
+        "json" => Box::new(OutputJson::new(config.output_prefix, 10000)),
+        "sqlite" => Box::new(OutputSqlite::new(config.output_prefix, 10000)),
+        "bulk" => Box::new(OutputBulk::new(config.output_prefix, config.index, 10000)),
+        "http" => Box::new(OutputHttp::new(
+            config.output_prefix,
+            HttpSettings {
+                url: config.url.to_string(),
+                index: config.index.to_string(),
+                concurrency: config.concurrency,
+                batch_size: config.batch_size,
+                max_retries: config.max_retries,
+            },
+        )),
+        "wikiextractor" => Box::new(OutputWikiExtractor::new(
+            config.output_prefix,
+            false,
+            config.bytes,
+        )),
+        "wikiextractor-json" => Box::new(OutputWikiExtractor::new(
+            config.output_prefix,
+            true,
+            config.bytes,
+        )),
+        "markdown" => Box::new(OutputMarkdown::new(config.output_prefix)),
+        "chunk" => Box::new(OutputChunk::new(config.output_prefix, config.chunk, 10000)),
+        "text" => Box::new(OutputText::new(
+            config.output_prefix,
+            config.text.clone(),
+            10000,
+        )),
+        _ => {
+            warn!("Unknown format [{}].", config.format);
+            panic!("Unknown format [{}].", config.format);
+        }
+    }
 }
 fn main() {
     if env::var("RUST_LOG").is_err() {
@@ -32,18 +287,64 @@ fn main() {
     env_logger::init();
     let args: Vec<String> = env::args().collect();
 
-    let (path, output_path) = parse_config(&args);
+    let config = parse_config(&args);
+    let mut template_rules = TemplateRules::default();
+    if let Some(path) = config.template_rules {
+        template_rules.load(path);
+    }
+    let mut output = create_output(&config);
+    // coordinates of the pages, written with any format
+    let mut geojson = if config.geojson {
+        Some(OutputGeoJson::new(config.output_prefix))
+    } else {
+        None
+    };
 
-    parse_wiki(path, output_path);
+    parse_wiki(
+        &config,
+        &template_rules,
+        output.as_mut(),
+        geojson.as_mut().map(|g| g as &mut dyn Output),
+    );
 
     info!("Finish wiki-extractor. ");
 }
 
-fn parse_wiki(path: &str, output_path: &str) {
-    let file = File::open(path).unwrap();
+// first pass: Template pages and the dump timestamp for template expansion
+fn collect_templates<'a>(
+    config: &Config,
+    template_rules: &'a TemplateRules,
+) -> TemplateExpander<'a> {
+    let mut expander = TemplateExpander::new(config.expand.clone(), template_rules);
+    let file = File::open(config.input_file).unwrap();
+    for page in WikiPageIterator::new(BzDecoder::new(file)) {
+        expander.add_timestamp(page.timestamp.as_str());
+        if page.title.starts_with("Template:") {
+            expander.add_template(page.title.as_str(), page.raw_content.as_str());
+        }
+    }
+    info!(
+        "Collected templates. [{}] - Timestamp:[{}]",
+        expander.len(),
+        expander.timestamp()
+    );
+    expander
+}
+
+fn parse_wiki(
+    config: &Config,
+    template_rules: &TemplateRules,
+    output: &mut dyn Output,
+    mut geojson: Option<&mut dyn Output>,
+) {
+    let expander = if config.expand_templates {
+        Some(collect_templates(config, template_rules))
+    } else {
+        None
+    };
+    let file = File::open(config.input_file).unwrap();
     let buf = BzDecoder::new(file);
     //let buf = BufReader::new(file);
-    let mut output = OutputJson::new(output_path, 10000);
 
     //TODO need a flag to skip Wikipedia special page that starts "Wikipedia:" in title.
     let _xml_parser = WikiPageIterator::new(buf);
@@ -56,142 +357,258 @@ fn parse_wiki(path: &str, output_path: &str) {
                 page.id, page.title, page.timestamp, page.meta
             );
 
-            let result = create_ja_config().parse(page.raw_content.as_str());
-            let mut page_content = String::new();
-            let mut doc: Document = Document {
-                id: page.id.to_string(),
-                title: page.title.to_string(),
-                timestamp: page.timestamp.to_string(),
-                revision_id: page.revision_id.to_string(),
-                url: format!(
-                    "https://ja.wikipedia.org/wiki?curid={}",
-                    page.id.to_string()
-                ),
-                contents: vec![],
-                categories: vec![],
-                headings: vec![],
-                images: vec![],
-                links: vec![],
-                redirect_to: None,
+            // node positions are in the expanded text, source_map maps them to raw_content
+            let (wikitext, source_map) = match &expander {
+                Some(expander) => {
+                    expander.expand_page(page.raw_content.as_str(), page.title.as_str())
+                }
+                None => (page.raw_content.to_string(), SourceMap::default()),
             };
+            let wikitext = replace_markers(wikitext);
+            // ages are counted to the dump timestamp as #time when templates are expanded,
+            // otherwise to the revision time of the page
+            let today = match &expander {
+                Some(expander) => expander.timestamp(),
+                None => page.timestamp.as_str(),
+            };
+            let context = TemplateContext {
+                rules: template_rules,
+                today: DateTime::parse(today),
+            };
+            let (doc, sections) =
+                parse_page(config, &context, &page, wikitext.as_str(), &source_map);
+            if let Some(geojson) = geojson.as_deref_mut() {
+                geojson.output(&doc);
+            }
+            for doc in split_document(doc, &sections, &config.granularity) {
+                output.output(&doc);
+            }
+        //print_doc(&doc);
+        } else {
+            info!(
+                "Skip : Id[{}] - Title:[{}] - Timestamp:[{}] - meta?:[{}]",
+                page.id, page.title, page.timestamp, page.meta
+            );
+        }
+    }
+    output.flush();
+    if let Some(geojson) = geojson {
+        geojson.flush();
+    }
+}
 
-            for node in result.nodes {
-                //match node {}
-                match node {
-                    Node::Category { target, .. } => doc
-                        .categories
-                        .push(target.replace("Category:", "").trim().to_string()),
+// parses a page into a document and its section boundaries
+fn parse_page(
+    config: &Config,
+    context: &TemplateContext,
+    page: &Page,
+    wikitext: &str,
+    source_map: &SourceMap,
+) -> (Document, Vec<SectionInfo>) {
+    let result = create_ja_config().parse(wikitext);
+    let mut page_content = String::new();
+    let mut doc: Document = Document {
+        id: page.id.to_string(),
+        title: page.title.to_string(),
+        timestamp: page.timestamp.to_string(),
+        revision_id: page.revision_id.to_string(),
+        url: format!(
+            "https://ja.wikipedia.org/wiki?curid={}",
+            page.id.to_string()
+        ),
+        contents: vec![],
+        categories: vec![],
+        headings: vec![],
+        images: vec![],
+        links: vec![],
+        annotations: vec![],
+        provenance: vec![],
+        tables: vec![],
+        references: vec![],
+        footnotes: vec![],
+        citations: vec![],
+        identifiers: vec![],
+        authority_ids: vec![],
+        coordinates: vec![],
+        dates: vec![],
+        readings: vec![],
+        infobox: vec![],
+        sections: vec![],
+        abstract_text: None,
+        redirect_to: None,
+        markdown: None,
+        passage: None,
+    };
+    (doc.references, doc.footnotes, doc.citations) =
+        parse_references(context, &result.nodes, wikitext);
+    doc.identifiers = parse_identifiers(&result.nodes);
+    doc.coordinates = parse_coordinates(context.rules, &result.nodes);
+    doc.dates = parse_dates(context.rules, &result.nodes);
+    doc.readings = parse_readings(context.rules, page.title.as_str(), &result.nodes);
+    let mut sections = vec![SectionInfo::new(0, &doc)];
+    if config.markdown {
+        doc.markdown = Some(parse_markdown(context, &page.title, &result.nodes));
+    }
 
-                    Node::CharacterEntity { character, .. } => page_content.push(character),
+    for node in result.nodes {
+        let (start, end) = node_range(&node);
+        let (source_start, source_end) = source_map.source_range(start, end);
+        let section_count = sections.len();
+        let content_start = page_content.len();
+        let footnotes = nested_refs(&node).len();
+        //match node {}
+        match node {
+            Node::Category { target, .. } => doc
+                .categories
+                .push(target.replace("Category:", "").trim().to_string()),
 
-                    Node::Text { value, .. } => {
-                        if value != "\n" {
-                            page_content.push_str(value)
-                        }
-                    }
-                    Node::Heading { nodes, .. } => {
-                        doc.contents.push(page_content.to_string());
-                        page_content.clear();
-                        let heading = extract_heading_text(&nodes);
-                        add_heading(heading.as_str(), &mut page_content, &mut doc);
-                    }
-                    Node::ExternalLink { nodes, .. } => {
-                        let link_text = extract_external_link_text(&nodes);
-                        page_content.push_str(link_text.clone_text().as_str());
-                        doc.links.push(link_text);
-                    }
-                    Node::Image { target, text, .. } => {
-                        // need to parse recursive in text
-                        doc.images.push(extract_image(target, &text));
-                    }
-                    Node::Link { target, text, .. } => {
-                        let link_text = extract_link_text(target, &text);
-                        page_content.push_str(link_text.clone_text().as_str());
-                        doc.links.push(link_text);
-                    }
+            Node::CharacterEntity { character, .. } => page_content.push(character),
 
-                    Node::UnorderedList { items, .. } => {
-                        for parsed_item in parse_items(items, &mut doc, 1) {
-                            page_content.push_str("\n  ");
-                            page_content.push_str(parsed_item.as_str());
-                        }
-                    }
-                    Node::OrderedList { items, .. } => {
-                        for parsed_item in parse_order_items(items, &mut doc, 1) {
-                            page_content.push_str("\n  ");
-                            page_content.push_str(parsed_item.as_str());
-                        }
-                    }
-                    Node::DefinitionList { items, .. } => {
-                        for parsed_item in parse_definition_items(items, &mut doc, 1) {
-                            page_content.push_str("\n  ");
-                            page_content.push_str(parsed_item.as_str());
-                        }
-                    }
-                    // TODO contentをパラグラフごとに分割するなら、ここで区切る?
-                    Node::ParagraphBreak { .. } => {
-                        page_content.push_str("\n");
-                    }
-                    Node::Template {
-                        name, parameters, ..
-                    } => {
-                        if let Some(template) = parse_template(&name, &parameters) {
-                            page_content.push_str(template.as_str());
-                        }
-                    }
-                    Node::Redirect { target, .. } => {
-                        doc.redirect_to = Some(target.to_string());
-                    }
+            Node::Text { value, .. } => {
+                if value != "\n" {
+                    page_content.push_str(value)
+                }
+            }
+            Node::Heading { nodes, level, .. } => {
+                doc.contents.push(page_content.to_string());
+                page_content.clear();
+                sections.push(SectionInfo::new(level, &doc));
+                let heading = extract_heading_text(&nodes);
+                add_heading(heading.as_str(), &mut page_content, &mut doc);
+            }
+            Node::ExternalLink { nodes, .. } => {
+                let link_text = extract_external_link_text(context, &nodes);
+                push_link_text(&mut page_content, link_text, &mut doc);
+            }
+            Node::Image { target, text, .. } => {
+                // need to parse recursive in text
+                doc.images.push(extract_image(target, &text));
+            }
+            Node::Link { target, text, .. } => {
+                let link_text = extract_link_text(target, &text);
+                push_link_text(&mut page_content, link_text, &mut doc);
+            }
 
-                    // // TODO Need extracte cells
-                    // Node::Table { .. } => {}
-                    //
-                    // Node::Tag { .. } => {}
-                    //
-                    // // TODO template combination?
-
-                    // Node::Parameter { .. } => {}
-                    // //TODO
-                    // Node::HorizontalDivider { .. } => {}
-                    // Node::MagicWord { .. } => {}
-                    // Node::Preformatted { .. } => {}
-                    //
-                    // TODO maybe NO-OP
-                    Node::StartTag { .. } => {}
-                    Node::EndTag { .. } => {}
-                    //NO-OP
-                    Node::Bold { .. } => {}
-                    Node::BoldItalic { .. } => {}
-                    Node::Italic { .. } => {}
-                    Node::Comment { .. } => {}
-                    _ => {
-                        trace!("あ    {:?}", node);
-                    }
+            Node::UnorderedList { items, .. } => {
+                for parsed_item in parse_items(context, items, &mut doc, 1) {
+                    page_content.push_str("\n  ");
+                    page_content.push_str(parsed_item.as_str());
+                }
+            }
+            Node::OrderedList { items, .. } => {
+                for parsed_item in parse_order_items(context, items, &mut doc, 1) {
+                    page_content.push_str("\n  ");
+                    page_content.push_str(parsed_item.as_str());
                 }
             }
+            Node::DefinitionList { items, .. } => {
+                for parsed_item in parse_definition_items(context, items, &mut doc, 1) {
+                    page_content.push_str("\n  ");
+                    page_content.push_str(parsed_item.as_str());
+                }
+            }
+            Node::ParagraphBreak { .. } => {
+                if let Some(section) = sections.last_mut() {
+                    section.paragraph_breaks.push(page_content.len());
+                }
+                page_content.push_str("\n");
+            }
+            Node::Template {
+                name, parameters, ..
+            } => match parse_template_rule(context.rules, &name) {
+                Some(TemplateRule::Infobox) => {
+                    doc.infobox.push(parse_infobox(context, &name, &parameters))
+                }
+                Some(TemplateRule::AuthorityControl) => doc
+                    .authority_ids
+                    .extend(parse_authority_control(&parameters)),
+                _ => push_template_text(context, &mut page_content, &name, &parameters),
+            },
+            Node::Redirect { target, .. } => {
+                doc.redirect_to = Some(target.to_string());
+            }
 
-            if result.warnings.is_empty() == false {
-                for warning in result.warnings {
-                    debug!(
-                        "[WARN] {} start:{} - end:{}",
-                        warning.message, warning.start, warning.end
-                    );
+            Node::Table { captions, rows, .. } => {
+                let table = parse_table(context, sections.len() - 1, &captions, &rows);
+                // a table is written as its own paragraph
+                if let Some(format) = &config.table_format {
+                    if let Some(section) = sections.last_mut() {
+                        section.paragraph_breaks.push(page_content.len());
+                        page_content.push('\n');
+                        page_content.push_str(linearize_table(&table, format).as_str());
+                        section.paragraph_breaks.push(page_content.len());
+                        page_content.push('\n');
+                    }
                 }
+                doc.tables.push(table);
             }
+            // Node::Tag { .. } => {}
+            //
+            // // TODO template combination?
 
-            if page_content.is_empty() == false && doc.contents.len() > 0 {
-                doc.contents.push(page_content.to_string());
+            // Node::Parameter { .. } => {}
+            // //TODO
+            // Node::HorizontalDivider { .. } => {}
+            // Node::MagicWord { .. } => {}
+            // Node::Preformatted { .. } => {}
+            //
+            // TODO maybe NO-OP
+            Node::StartTag { name, .. } => push_tag(&mut page_content, &name),
+            Node::EndTag { name, .. } => push_end_tag(&mut page_content, &name),
+            Node::Bold { .. } => page_content.push(BOLD),
+            Node::BoldItalic { .. } => {
+                page_content.push(BOLD);
+                page_content.push(ITALIC);
             }
-            output.output(&doc);
-        //print_doc(&doc);
+            Node::Italic { .. } => page_content.push(ITALIC),
+            Node::Tag { name, .. } if name == "ref" => page_content.push(FOOTNOTE),
+            //NO-OP
+            Node::Comment { .. } => {}
+            _ => {
+                trace!("あ    {:?}", node);
+            }
+        }
+        push_footnotes(&mut page_content, footnotes);
+        // a heading starts a new section content
+        let content_start = if sections.len() > section_count {
+            0
         } else {
-            info!(
-                "Skip : Id[{}] - Title:[{}] - Timestamp:[{}] - meta?:[{}]",
-                page.id, page.title, page.timestamp, page.meta
+            content_start
+        };
+        if page_content.len() > content_start {
+            if let Some(section) = sections.last_mut() {
+                section.segments.push(Segment {
+                    start: content_start,
+                    end: page_content.len(),
+                    source_start,
+                    source_end,
+                });
+            }
+        }
+    }
+
+    if result.warnings.is_empty() == false {
+        for warning in result.warnings {
+            debug!(
+                "[WARN] {} start:{} - end:{}",
+                warning.message, warning.start, warning.end
             );
         }
     }
-    output.flush();
+
+    if page_content.is_empty() == false {
+        doc.contents.push(page_content.to_string());
+    }
+    doc.annotations = strip_annotations(&mut doc, &mut sections, &config.annotations);
+    if config.annotations.contains(&AnnotationType::Era) {
+        add_era_annotations(&mut doc);
+    }
+    if config.provenance {
+        doc.provenance = parse_provenance(&doc, &sections);
+    }
+    doc.sections = parse_sections(&doc, &sections);
+    doc.abstract_text = parse_abstract(&doc, &sections, &config.abstract_settings);
+    (doc, sections)
 }
 
 // for test
@@ -209,8 +626,73 @@ fn print_doc(doc: &Document) {
     //debug!("Links {:?}", doc.links)
 }
 
+// 和暦 dates in the contents and the infobox values
+fn add_era_annotations(doc: &mut Document) {
+    for (section_index, content) in doc.contents.iter().enumerate() {
+        doc.annotations.extend(parse_eras(section_index, content));
+    }
+    doc.annotations
+        .sort_by_key(|a| (a.section_index, a.start, a.end));
+    for infobox in doc.infobox.iter_mut() {
+        for value in infobox.parameters.values_mut() {
+            value.annotations = parse_eras(0, value.text.as_str());
+        }
+    }
+}
+
 fn add_heading(heading: &str, page_content: &mut String, doc: &mut Document) {
     doc.headings.push(heading.to_string());
     page_content.push_str(heading);
     page_content.push('\n');
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiki_extractor::parser::passage_parser::split_paragraphs;
+
+    fn page(raw_content: &str) -> Page {
+        Page {
+            id: String::from("1"),
+            title: String::from("スタブ"),
+            raw_content: raw_content.to_string(),
+            revision_id: String::from("10"),
+            timestamp: String::from("2020-01-01T00:00:00Z"),
+            meta: false,
+        }
+    }
+
+    #[test]
+    fn page_without_headings() {
+        let args: Vec<String> = vec!["wiki-extractor", "Cargo.toml", "out"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let config = parse_config(&args);
+        let rules = TemplateRules::default();
+        let context = TemplateContext {
+            rules: &rules,
+            today: None,
+        };
+        let wikitext = "'''スタブ'''は短い記事。<ref>出典</ref>\n\n次の段落。\n";
+        let (doc, sections) = parse_page(
+            &config,
+            &context,
+            &page(wikitext),
+            wikitext,
+            &SourceMap::default(),
+        );
+        assert_eq!(doc.contents.len(), 1);
+        assert!(doc.contents[0].starts_with("スタブは短い記事。"));
+        assert_eq!(doc.footnotes.len(), 1);
+        assert_eq!(doc.sections.len(), 1);
+        assert!(doc
+            .abstract_text
+            .as_deref()
+            .unwrap()
+            .starts_with("スタブは短い記事。"));
+        let paragraphs = split_paragraphs(&doc, &sections);
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(paragraphs[1].contents, vec![String::from("次の段落。")]);
+    }
+}
@@ -1 +1,16 @@
+use crate::parser::model::Document;
+
+pub mod output_bulk;
+pub mod output_chunk;
+pub mod output_geojson;
+pub mod output_http;
 pub mod output_json;
+pub mod output_markdown;
+pub mod output_sqlite;
+pub mod output_text;
+pub mod output_wikiextractor;
+
+pub trait Output {
+    fn output(&mut self, doc: &Document);
+    fn flush(&mut self);
+}
@@ -0,0 +1,197 @@
+use crate::output::Output;
+use crate::parser::model::Document;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Write Elasticsearch/OpenSearch `_bulk` request bodies.
+// Each document is an action line plus the document, using the page id as `_id`.
+pub struct OutputBulk {
+    path: String,
+    index: String,
+    file: BufWriter<File>,
+    counter: u16,
+    page_limit: u16,
+    file_counter: u16,
+}
+
+impl OutputBulk {
+    // Also writes the matching index template to `{path}_template.json`.
+    pub fn new(path: &str, index: &str, page_limit: u16) -> Self {
+        let template_path = format!("{}_template.json", &path);
+        let template = serde_json::to_string_pretty(&index_template(index)).unwrap();
+        let mut template_file = create_file(template_path.as_str());
+        writeln!(template_file, "{}", template)
+            .unwrap_or_else(|_| panic!("can't write file[{}]", template_path.as_str()));
+        template_file.flush().unwrap();
+
+        OutputBulk {
+            path: path.to_string(),
+            index: index.to_string(),
+            file: create_file(format!("{}_0.ndjson", &path).as_str()),
+            counter: 0,
+            page_limit,
+            file_counter: 0,
+        }
+    }
+}
+
+fn create_file(path: &str) -> BufWriter<File> {
+    BufWriter::new(
+        File::create(path)
+            .unwrap_or_else(|_| panic!("can't open file[{}] with write option", path)),
+    )
+}
+
+// The action line for a document
+pub fn bulk_action(index: &str, doc: &Document) -> Value {
+    json!({ "index": { "_index": index, "_id": doc.id } })
+}
+
+// Composable index template for `PUT _index_template/<name>`.
+// Requires the analysis-kuromoji plugin.
+pub fn index_template(index: &str) -> Value {
+    let ja_text = json!({ "type": "text", "analyzer": "ja_analyzer" });
+    json!({
+        "index_patterns": [format!("{}*", index)],
+        "template": {
+            "settings": {
+                "analysis": {
+                    "analyzer": {
+                        "ja_analyzer": {
+                            "type": "custom",
+                            "tokenizer": "kuromoji_tokenizer",
+                            "filter": [
+                                "kuromoji_baseform",
+                                "kuromoji_part_of_speech",
+                                "cjk_width",
+                                "ja_stop",
+                                "kuromoji_stemmer",
+                                "lowercase"
+                            ]
+                        }
+                    }
+                }
+            },
+            "mappings": {
+                "dynamic": false,
+                "properties": {
+                    "id": { "type": "keyword" },
+                    "title": {
+                        "type": "text",
+                        "analyzer": "ja_analyzer",
+                        "fields": { "keyword": { "type": "keyword" } }
+                    },
+                    "timestamp": { "type": "date" },
+                    "revision_id": { "type": "keyword" },
+                    "url": { "type": "keyword", "index": false },
+                    "contents": ja_text,
+                    "headings": ja_text,
+                    "categories": { "type": "keyword" },
+                    "links": {
+                        "properties": {
+                            "text": { "type": "keyword" },
+                            "link_target": { "type": "keyword" }
+                        }
+                    },
+                    "images": {
+                        "properties": {
+                            "target": { "type": "keyword" },
+                            "target_type": { "type": "keyword" },
+                            "text": {
+                                "properties": {
+                                    "text": ja_text,
+                                    "link_target": { "type": "keyword" }
+                                }
+                            }
+                        }
+                    },
+                    "redirect_to": { "type": "keyword" }
+                }
+            }
+        }
+    })
+}
+
+impl Output for OutputBulk {
+    fn output(&mut self, doc: &Document) {
+        let action = serde_json::to_string(&bulk_action(self.index.as_str(), doc)).unwrap();
+        let str = serde_json::to_string(doc).unwrap();
+        writeln!(self.file, "{}\n{}", action, str)
+            .unwrap_or_else(|_| panic!("can't write document[{}]", doc.id));
+        self.counter += 1;
+        if self.counter == self.page_limit {
+            self.file.flush().unwrap();
+            self.file_counter += 1;
+            let path = format!("{}_{}.ndjson", self.path, self.file_counter);
+            self.file = create_file(path.as_str());
+            self.counter = 0;
+        }
+    }
+
+    fn flush(&mut self) {
+        self.file.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> Document {
+        Document {
+            id: String::from("1"),
+            title: String::from("東京"),
+            contents: vec![String::from("東京は首都。")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn action_line() {
+        assert_eq!(
+            bulk_action("jawiki", &page()),
+            json!({ "index": { "_index": "jawiki", "_id": "1" } })
+        );
+    }
+
+    #[test]
+    fn bulk_file() {
+        let path = std::env::temp_dir().join(format!("bulk_test_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut output = OutputBulk::new(path, "jawiki", 100);
+        output.output(&page());
+        output.flush();
+        let bulk = std::fs::read_to_string(format!("{}_0.ndjson", path)).unwrap();
+        let template = std::fs::read_to_string(format!("{}_template.json", path)).unwrap();
+        std::fs::remove_file(format!("{}_0.ndjson", path)).unwrap();
+        std::fs::remove_file(format!("{}_template.json", path)).unwrap();
+
+        let lines: Vec<Value> = bulk
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["index"]["_id"], "1");
+        assert_eq!(lines[1]["id"], "1");
+        assert_eq!(lines[1]["title"], "東京");
+        assert_eq!(lines[1]["contents"], json!(["東京は首都。"]));
+
+        let template: Value = serde_json::from_str(template.as_str()).unwrap();
+        assert_eq!(template, index_template("jawiki"));
+    }
+
+    #[test]
+    fn template() {
+        let template = index_template("jawiki");
+        assert_eq!(template["index_patterns"], json!(["jawiki*"]));
+        let analyzer = &template["template"]["settings"]["analysis"]["analyzer"]["ja_analyzer"];
+        assert_eq!(analyzer["tokenizer"], "kuromoji_tokenizer");
+        let properties = &template["template"]["mappings"]["properties"];
+        for field in &["title", "contents", "headings"] {
+            assert_eq!(properties[field]["analyzer"], "ja_analyzer");
+        }
+        assert_eq!(properties["title"]["fields"]["keyword"]["type"], "keyword");
+        assert_eq!(properties["categories"]["type"], "keyword");
+    }
+}
@@ -0,0 +1,379 @@
+use crate::output::Output;
+use crate::parser::model::Document;
+use crate::parser::sentence_parser::split_sentences;
+use log::warn;
+use serde_derive::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+#[derive(Clone, Copy)]
+pub enum ChunkUnit {
+    Char,
+    // approximate tokens: one per CJK character, one per 4 other characters
+    Token,
+}
+
+impl FromStr for ChunkUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "char" => Ok(ChunkUnit::Char),
+            "token" => Ok(ChunkUnit::Token),
+            _ => {
+                warn!("Unexpected Chunk Unit: [{}]", s);
+                Err(())
+            }
+        }
+    }
+}
+
+impl ChunkUnit {
+    fn size(&self, text: &str) -> usize {
+        match self {
+            ChunkUnit::Char => text.chars().count(),
+            ChunkUnit::Token => {
+                let mut tokens = 0;
+                let mut others: usize = 0;
+                for c in text.chars() {
+                    if is_cjk(c) {
+                        tokens += 1;
+                    } else if !c.is_whitespace() {
+                        others += 1;
+                    }
+                }
+                tokens + others.div_ceil(4)
+            }
+        }
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3000}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}' | '\u{ff00}'..='\u{ffef}')
+}
+
+#[derive(Clone, Copy)]
+pub struct ChunkSettings {
+    pub size: usize,
+    pub overlap: usize,
+    pub unit: ChunkUnit,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Chunk {
+    // `<page_id>#<section_index>-<chunk_index>`
+    pub id: String,
+    pub page_id: String,
+    pub revision_id: String,
+    pub url: String,
+    pub title: String,
+    pub section_index: usize,
+    pub chunk_index: usize,
+    // "Title > Heading > Subheading"
+    pub breadcrumb: String,
+    // character offsets of the chunk in the section text
+    pub start: usize,
+    pub end: usize,
+    // breadcrumb and chunk text
+    pub text: String,
+}
+
+// Split section text into chunks on sentence boundaries.
+// returns byte ranges of the chunks
+pub fn chunk_ranges(text: &str, budget: usize, settings: &ChunkSettings) -> Vec<(usize, usize)> {
+    let sentences = sentence_ranges(text, budget, &settings.unit);
+    let mut chunks = vec![];
+    let mut first = 0;
+    while first < sentences.len() {
+        let mut last = first;
+        while last + 1 < sentences.len()
+            && settings
+                .unit
+                .size(&text[sentences[first].0..sentences[last + 1].1])
+                <= budget
+        {
+            last += 1;
+        }
+        chunks.push((sentences[first].0, sentences[last].1));
+        if last + 1 >= sentences.len() {
+            break;
+        }
+        // start the next chunk with the last sentences within the overlap
+        let mut next = last + 1;
+        while next > first + 1
+            && settings
+                .unit
+                .size(&text[sentences[next - 1].0..sentences[last].1])
+                <= settings.overlap
+        {
+            next -= 1;
+        }
+        // drop overlap sentences the next sentence doesn't fit with, so every chunk adds text
+        while next <= last
+            && settings
+                .unit
+                .size(&text[sentences[next].0..sentences[last + 1].1])
+                > budget
+        {
+            next += 1;
+        }
+        first = next;
+    }
+    chunks
+}
+
+// byte ranges of sentences, sentences over the budget are cut
+fn sentence_ranges(text: &str, budget: usize, unit: &ChunkUnit) -> Vec<(usize, usize)> {
+    let mut ranges = vec![];
+    for sentence in split_sentences(text) {
+        let start = sentence.as_ptr() as usize - text.as_ptr() as usize;
+        let mut piece_start = start;
+        let mut piece_end = start;
+        for (index, c) in sentence.char_indices() {
+            let end = start + index + c.len_utf8();
+            if piece_end > piece_start && unit.size(&text[piece_start..end]) > budget {
+                ranges.push((piece_start, piece_end));
+                piece_start = piece_end;
+            }
+            piece_end = end;
+        }
+        if piece_end > piece_start {
+            ranges.push((piece_start, piece_end));
+        }
+    }
+    ranges
+}
+
+// Write retrieval chunks of section records as JSON lines.
+pub struct OutputChunk {
+    path: String,
+    settings: ChunkSettings,
+    file: BufWriter<File>,
+    counter: u16,
+    page_limit: u16,
+    file_counter: u16,
+}
+
+impl OutputChunk {
+    // page_limit is the number of sections per file
+    pub fn new(path: &str, settings: ChunkSettings, page_limit: u16) -> Self {
+        OutputChunk {
+            path: path.to_string(),
+            settings,
+            file: create_file(format!("{}_0.jsonl", &path).as_str()),
+            counter: 0,
+            page_limit,
+            file_counter: 0,
+        }
+    }
+
+    pub fn chunks(&self, doc: &Document) -> Vec<Chunk> {
+        let passage = match doc.passage.as_ref() {
+            Some(passage) => passage,
+            None => {
+                warn!("Chunk output needs section records. id[{}]", doc.id);
+                return vec![];
+            }
+        };
+        let text = match doc.contents.first() {
+            Some(text) => text.as_str(),
+            None => return vec![],
+        };
+        let mut breadcrumb = vec![doc.title.as_str()];
+        breadcrumb.extend(passage.heading_path.iter().map(|h| h.as_str()));
+        // the breadcrumb and the line break take at most half of the size
+        let breadcrumb = fit_breadcrumb(
+            breadcrumb.join(" > ").as_str(),
+            self.settings.size / 2,
+            &self.settings.unit,
+        );
+        let prefix_size = if breadcrumb.is_empty() {
+            0
+        } else {
+            self.settings.unit.size(breadcrumb.as_str()) + 1
+        };
+        let budget = self.settings.size.saturating_sub(prefix_size).max(1);
+        chunk_ranges(text, budget, &self.settings)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (start, end))| Chunk {
+                id: format!("{}#{}-{}", passage.page_id, passage.section_index, index),
+                page_id: passage.page_id.to_string(),
+                revision_id: doc.revision_id.to_string(),
+                url: doc.url.to_string(),
+                title: doc.title.to_string(),
+                section_index: passage.section_index,
+                chunk_index: index,
+                breadcrumb: breadcrumb.to_string(),
+                start: text[..start].chars().count(),
+                end: text[..end].chars().count(),
+                text: if breadcrumb.is_empty() {
+                    text[start..end].to_string()
+                } else {
+                    format!("{}\n{}", breadcrumb, &text[start..end])
+                },
+            })
+            .collect()
+    }
+}
+
+// The breadcrumb and a line break within max_size, cut with "…" if it is longer.
+// Empty if even "…" does not fit.
+fn fit_breadcrumb(breadcrumb: &str, max_size: usize, unit: &ChunkUnit) -> String {
+    // + 1 for the line break
+    if unit.size(breadcrumb) < max_size {
+        return breadcrumb.to_string();
+    }
+    let mut chars: Vec<char> = breadcrumb.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let cut = format!("{}…", chars.iter().collect::<String>().trim_end());
+        if unit.size(cut.as_str()) < max_size {
+            return cut;
+        }
+    }
+    String::new()
+}
+
+fn create_file(path: &str) -> BufWriter<File> {
+    BufWriter::new(
+        File::create(path)
+            .unwrap_or_else(|_| panic!("can't open file[{}] with write option", path)),
+    )
+}
+
+impl Output for OutputChunk {
+    fn output(&mut self, doc: &Document) {
+        if doc.redirect_to.is_some() {
+            return;
+        }
+        for chunk in self.chunks(doc) {
+            let str = serde_json::to_string(&chunk).unwrap();
+            writeln!(self.file, "{}", str)
+                .unwrap_or_else(|_| panic!("can't write chunk[{}]", chunk.id));
+        }
+        self.counter += 1;
+        if self.counter == self.page_limit {
+            self.file.flush().unwrap();
+            self.file_counter += 1;
+            let path = format!("{}_{}.jsonl", self.path, self.file_counter);
+            self.file = create_file(path.as_str());
+            self.counter = 0;
+        }
+    }
+
+    fn flush(&mut self) {
+        self.file.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::model::Passage;
+
+    fn settings(size: usize, overlap: usize) -> ChunkSettings {
+        ChunkSettings {
+            size,
+            overlap,
+            unit: ChunkUnit::Char,
+        }
+    }
+
+    fn chunk_texts<'a>(text: &'a str, budget: usize, settings: &ChunkSettings) -> Vec<&'a str> {
+        chunk_ranges(text, budget, settings)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn unit_size() {
+        assert_eq!(ChunkUnit::Char.size("日本語 text"), 8);
+        // 3 CJK characters and 4 other characters
+        assert_eq!(ChunkUnit::Token.size("日本語 text"), 4);
+        assert_eq!(ChunkUnit::Token.size("abcde"), 2);
+    }
+
+    #[test]
+    fn sentences_within_budget() {
+        let text = "一文目。二文目。三文目。";
+        assert_eq!(
+            chunk_texts(text, 8, &settings(8, 0)),
+            vec!["一文目。二文目。", "三文目。"]
+        );
+        assert_eq!(chunk_texts(text, 100, &settings(100, 0)), vec![text]);
+    }
+
+    #[test]
+    fn overlap() {
+        let text = "一文目。二文目。三文目。";
+        assert_eq!(
+            chunk_texts(text, 8, &settings(8, 4)),
+            vec!["一文目。二文目。", "二文目。三文目。"]
+        );
+        // the overlap never repeats the whole previous chunk
+        assert_eq!(
+            chunk_texts(text, 4, &settings(4, 4)),
+            vec!["一文目。", "二文目。", "三文目。"]
+        );
+        // the next sentence doesn't fit with the overlap
+        assert_eq!(
+            chunk_texts("一文目。二。長い長い長い長い長い。", 8, &settings(8, 2)),
+            vec!["一文目。二。", "長い長い長い長い", "長い。"]
+        );
+    }
+
+    #[test]
+    fn long_sentence() {
+        let text = "とても長い一文です。";
+        assert_eq!(
+            chunk_texts(text, 4, &settings(4, 0)),
+            vec!["とても長", "い一文で", "す。"]
+        );
+    }
+
+    #[test]
+    fn breadcrumb() {
+        assert_eq!(fit_breadcrumb("A > B", 10, &ChunkUnit::Char), "A > B");
+        assert_eq!(
+            fit_breadcrumb("Title > Heading", 8, &ChunkUnit::Char),
+            "Title…"
+        );
+        assert_eq!(fit_breadcrumb("Title", 1, &ChunkUnit::Char), "");
+    }
+
+    #[test]
+    fn chunks_fit_the_size() {
+        let path = std::env::temp_dir().join(format!("chunk_test_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let output = OutputChunk::new(path, settings(16, 0), 100);
+        let doc = Document {
+            title: String::from("とても長いタイトル"),
+            contents: vec![String::from("一文目。二文目。三文目。")],
+            passage: Some(Passage {
+                page_id: String::from("1"),
+                section_index: 2,
+                paragraph_index: None,
+                level: 2,
+                heading_path: vec![String::from("歴史")],
+            }),
+            ..Default::default()
+        };
+        let chunks = output.chunks(&doc);
+        std::fs::remove_file(format!("{}_0.jsonl", path)).unwrap();
+        assert_eq!(chunks[0].id, "1#2-0");
+        // the breadcrumb and the line break take at most 8 characters, the text takes the rest
+        assert_eq!(chunks[0].breadcrumb, "とても長いタ…");
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start, chunks[0].end), (0, 8));
+        assert_eq!((chunks[1].start, chunks[1].end), (8, 12));
+        assert_eq!(chunks[1].text, "とても長いタ…\n三文目。");
+        for chunk in chunks.iter() {
+            assert!(ChunkUnit::Char.size(&chunk.text) <= 16);
+        }
+    }
+}
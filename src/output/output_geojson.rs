@@ -0,0 +1,62 @@
+use crate::output::Output;
+use crate::parser::model::Document;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Write the coordinates of all pages to `{path}.geojson` as one FeatureCollection.
+// A page with several coordinates is several features.
+pub struct OutputGeoJson {
+    file: BufWriter<File>,
+    counter: usize,
+}
+
+impl OutputGeoJson {
+    pub fn new(path: &str) -> Self {
+        let file_path = format!("{}.geojson", path);
+        let mut file = BufWriter::new(File::create(file_path.as_str()).unwrap_or_else(|_| {
+            panic!("can't open file[{}] with write option", file_path.as_str())
+        }));
+        write!(file, "{{\"type\":\"FeatureCollection\",\"features\":[")
+            .unwrap_or_else(|_| panic!("can't write file[{}]", file_path.as_str()));
+        OutputGeoJson { file, counter: 0 }
+    }
+}
+
+// GeoJSON positions are [longitude, latitude]
+pub fn features(doc: &Document) -> Vec<Value> {
+    doc.coordinates
+        .iter()
+        .map(|coordinate| {
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [coordinate.longitude, coordinate.latitude]
+                },
+                "properties": {
+                    "id": doc.id,
+                    "title": doc.title,
+                    "url": doc.url,
+                    "source": coordinate.source,
+                    "title_coordinate": coordinate.title
+                }
+            })
+        })
+        .collect()
+}
+
+impl Output for OutputGeoJson {
+    fn output(&mut self, doc: &Document) {
+        for feature in features(doc) {
+            let separator = if self.counter == 0 { "\n" } else { ",\n" };
+            write!(self.file, "{}{}", separator, feature).expect("can't write geojson");
+            self.counter += 1;
+        }
+    }
+
+    fn flush(&mut self) {
+        writeln!(self.file, "\n]}}").expect("can't write geojson");
+        self.file.flush().expect("can't write geojson");
+    }
+}
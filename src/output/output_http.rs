@@ -0,0 +1,364 @@
+use crate::output::output_bulk::bulk_action;
+use crate::output::Output;
+use crate::parser::model::Document;
+use log::{info, warn};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const INITIAL_BACKOFF_MILLIS: u64 = 500;
+const MAX_BACKOFF_MILLIS: u64 = 30_000;
+
+// One document in a `_bulk` request. `body` is the action line plus the document.
+struct BulkItem {
+    id: String,
+    body: String,
+}
+
+pub struct HttpSettings {
+    pub url: String,
+    pub index: String,
+    pub concurrency: usize,
+    pub batch_size: usize,
+    pub max_retries: u32,
+}
+
+// POST documents to a `_bulk` endpoint.
+// Batches are sent by `concurrency` worker threads. Requests answered with 429/5xx
+// are retried with exponential backoff, and so are items rejected with 429/5xx.
+// Items that still fail are written to `{path}_failed.ndjson` as a replayable bulk body.
+pub struct OutputHttp {
+    settings: Arc<HttpSettings>,
+    failed: Arc<Mutex<BufWriter<File>>>,
+    batch: Vec<BulkItem>,
+    sender: Option<SyncSender<Vec<BulkItem>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl OutputHttp {
+    pub fn new(path: &str, settings: HttpSettings) -> Self {
+        let failed_path = format!("{}_failed.ndjson", &path);
+        let failed = File::create(failed_path.as_str())
+            .unwrap_or_else(|_| panic!("can't open file[{}] with write option", failed_path));
+        OutputHttp {
+            settings: Arc::new(settings),
+            failed: Arc::new(Mutex::new(BufWriter::new(failed))),
+            batch: vec![],
+            sender: None,
+            workers: vec![],
+        }
+    }
+
+    fn start_workers(&mut self) {
+        let (sender, receiver) = sync_channel::<Vec<BulkItem>>(self.settings.concurrency);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..self.settings.concurrency.max(1) {
+            let receiver = receiver.clone();
+            let settings = self.settings.clone();
+            let failed = self.failed.clone();
+            self.workers.push(thread::spawn(move || {
+                run_worker(receiver, settings, failed)
+            }));
+        }
+        self.sender = Some(sender);
+    }
+
+    fn send_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        if self.sender.is_none() {
+            self.start_workers();
+        }
+        let batch = std::mem::take(&mut self.batch);
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(batch)
+            .expect("bulk workers stopped unexpectedly");
+    }
+}
+
+fn run_worker(
+    receiver: Arc<Mutex<Receiver<Vec<BulkItem>>>>,
+    settings: Arc<HttpSettings>,
+    failed: Arc<Mutex<BufWriter<File>>>,
+) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(10))
+        .timeout(Duration::from_secs(120))
+        .build();
+    loop {
+        let batch = match receiver.lock().unwrap().recv() {
+            Ok(batch) => batch,
+            Err(_) => break,
+        };
+        let failed_items = post_with_retry(&agent, &settings, batch);
+        if !failed_items.is_empty() {
+            let mut failed = failed.lock().unwrap();
+            for item in failed_items {
+                write!(failed, "{}", item.body).expect("can't write failed item");
+            }
+            failed.flush().expect("can't write failed item");
+        }
+    }
+}
+
+// returns the items that could not be indexed
+fn post_with_retry(
+    agent: &ureq::Agent,
+    settings: &HttpSettings,
+    mut items: Vec<BulkItem>,
+) -> Vec<BulkItem> {
+    let mut failed_items = vec![];
+    let mut attempt = 0;
+    loop {
+        let body: String = items.iter().map(|item| item.body.as_str()).collect();
+        let retry_items = match agent
+            .post(settings.url.as_str())
+            .set("Content-Type", "application/x-ndjson")
+            .send_string(body.as_str())
+        {
+            Ok(response) => {
+                let response: Value = match response
+                    .into_string()
+                    .map_err(|e| e.to_string())
+                    .and_then(|body| serde_json::from_str(body.as_str()).map_err(|e| e.to_string()))
+                {
+                    Ok(response) => response,
+                    Err(e) => {
+                        warn!("Can't parse bulk response: {}", e);
+                        failed_items.append(&mut items);
+                        return failed_items;
+                    }
+                };
+                let (retry_items, mut rejected) = split_item_results(items, &response);
+                failed_items.append(&mut rejected);
+                if retry_items.is_empty() {
+                    return failed_items;
+                }
+                retry_items
+            }
+            Err(ureq::Error::Status(status, _)) if status == 429 || status >= 500 => {
+                warn!("Bulk request failed with status [{}]", status);
+                items
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                warn!(
+                    "Bulk request rejected with status [{}]: {}",
+                    status,
+                    response.into_string().unwrap_or_default()
+                );
+                failed_items.append(&mut items);
+                return failed_items;
+            }
+            Err(e) => {
+                warn!("Bulk request failed: {}", e);
+                items
+            }
+        };
+
+        if attempt >= settings.max_retries {
+            warn!(
+                "Give up {} items after {} retries",
+                retry_items.len(),
+                attempt
+            );
+            failed_items.extend(retry_items);
+            return failed_items;
+        }
+        let backoff = INITIAL_BACKOFF_MILLIS
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_BACKOFF_MILLIS);
+        attempt += 1;
+        info!(
+            "Retry {} items in {}ms (attempt {})",
+            retry_items.len(),
+            backoff,
+            attempt
+        );
+        thread::sleep(Duration::from_millis(backoff));
+        items = retry_items;
+    }
+}
+
+// Split items by the per-item results of a bulk response.
+// returns (items to retry, items rejected)
+fn split_item_results(items: Vec<BulkItem>, response: &Value) -> (Vec<BulkItem>, Vec<BulkItem>) {
+    let mut retry_items = vec![];
+    let mut rejected = vec![];
+    if response["errors"].as_bool() != Some(true) {
+        return (retry_items, rejected);
+    }
+    let results = response["items"].as_array();
+    for (index, item) in items.into_iter().enumerate() {
+        let result = results
+            .and_then(|results| results.get(index))
+            .and_then(|result| result.as_object())
+            .and_then(|result| result.values().next());
+        let result = match result {
+            Some(result) => result,
+            None => {
+                warn!("Missing bulk item result for id[{}]", item.id);
+                rejected.push(item);
+                continue;
+            }
+        };
+        if result.get("error").is_none() {
+            continue;
+        }
+        match result["status"].as_u64() {
+            Some(status) if status == 429 || status >= 500 => retry_items.push(item),
+            status => {
+                warn!(
+                    "Bulk item id[{}] failed with status [{:?}]: {}",
+                    item.id, status, result["error"]
+                );
+                rejected.push(item);
+            }
+        }
+    }
+    (retry_items, rejected)
+}
+
+impl Output for OutputHttp {
+    fn output(&mut self, doc: &Document) {
+        let action =
+            serde_json::to_string(&bulk_action(self.settings.index.as_str(), doc)).unwrap();
+        let str = serde_json::to_string(doc).unwrap();
+        self.batch.push(BulkItem {
+            id: doc.id.to_string(),
+            body: format!("{}\n{}\n", action, str),
+        });
+        if self.batch.len() >= self.settings.batch_size {
+            self.send_batch();
+        }
+    }
+
+    // Send the pending batch and wait until every in-flight request is finished.
+    fn flush(&mut self) {
+        self.send_batch();
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            worker.join().expect("bulk worker panicked");
+        }
+        self.failed.lock().unwrap().flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    // Answer one request per connection with the given responses and return the request bodies
+    fn stub_server(responses: Vec<(u16, &'static str)>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/_bulk", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies = vec![];
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 4096];
+                let header_end = loop {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                };
+                let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+                let length: usize = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|value| value.trim().parse().unwrap())
+                    .unwrap_or(0);
+                while request.len() < header_end + length {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                bodies.push(String::from_utf8(request[header_end..].to_vec()).unwrap());
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+            bodies
+        });
+        (url, server)
+    }
+
+    fn document(id: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            title: format!("title{}", id),
+            ..Document::default()
+        }
+    }
+
+    #[test]
+    fn retries_requests_and_items() {
+        let (url, server) = stub_server(vec![
+            (429, "{}"),
+            (
+                200,
+                r#"{"errors":true,"items":[
+                    {"index":{"_id":"1","status":201}},
+                    {"index":{"_id":"2","status":429,"error":{"type":"es_rejected_execution_exception"}}},
+                    {"index":{"_id":"3","status":400,"error":{"type":"mapper_parsing_exception"}}},
+                    {"index":{"_id":"4","status":503,"error":{"type":"unavailable_shards_exception"}}}]}"#,
+            ),
+            (
+                200,
+                r#"{"errors":false,"items":[{"index":{"_id":"2","status":201}},{"index":{"_id":"4","status":201}}]}"#,
+            ),
+        ]);
+        let path = std::env::temp_dir().join(format!("output_http_test_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut output = OutputHttp::new(
+            path,
+            HttpSettings {
+                url,
+                index: String::from("test"),
+                concurrency: 1,
+                batch_size: 10,
+                max_retries: 3,
+            },
+        );
+        for id in ["1", "2", "3", "4"] {
+            output.output(&document(id));
+        }
+        output.flush();
+
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 3);
+        // the whole request is retried after 429
+        assert_eq!(bodies[0], bodies[1]);
+        assert_eq!(bodies[1].lines().count(), 8);
+        // only the items rejected with 429 and 503 are sent again
+        let ids: Vec<&str> = bodies[2]
+            .lines()
+            .filter(|line| line.contains("_index"))
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids[0].contains(r#""_id":"2""#));
+        assert!(ids[1].contains(r#""_id":"4""#));
+
+        let failed_path = format!("{}_failed.ndjson", path);
+        let failed = std::fs::read_to_string(failed_path.as_str()).unwrap();
+        std::fs::remove_file(failed_path).unwrap();
+        assert_eq!(failed.lines().count(), 2);
+        assert!(failed.contains(r#""_id":"3""#));
+        assert!(failed.contains(r#""title":"title3""#));
+    }
+}
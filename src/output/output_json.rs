@@ -1,3 +1,4 @@
+use crate::output::Output;
 use crate::parser::model::Document;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
@@ -27,8 +28,10 @@ impl OutputJson {
             file_counter: 0,
         }
     }
+}
 
-    pub fn output(&mut self, doc: &Document) {
+impl Output for OutputJson {
+    fn output(&mut self, doc: &Document) {
         let str = serde_json::to_string(doc).unwrap();
         writeln!(self.file, "{}", str);
         self.counter += 1;
@@ -45,7 +48,7 @@ impl OutputJson {
         }
     }
 
-    pub fn flush(&mut self) {
+    fn flush(&mut self) {
         self.file.flush();
     }
 }
@@ -0,0 +1,43 @@
+use crate::output::Output;
+use crate::parser::model::Document;
+use log::warn;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+// Write one Markdown file per page to `{dir}/{id}.md`.
+// Redirect pages and documents without markdown are skipped.
+pub struct OutputMarkdown {
+    dir: PathBuf,
+}
+
+impl OutputMarkdown {
+    pub fn new(dir: &str) -> Self {
+        fs::create_dir_all(dir).unwrap_or_else(|_| panic!("can't create directory[{}]", dir));
+        OutputMarkdown {
+            dir: PathBuf::from(dir),
+        }
+    }
+}
+
+impl Output for OutputMarkdown {
+    fn output(&mut self, doc: &Document) {
+        if doc.redirect_to.is_some() {
+            return;
+        }
+        let markdown = match doc.markdown.as_ref() {
+            Some(markdown) => markdown,
+            None => {
+                warn!("Skip document[{}] without markdown", doc.id);
+                return;
+            }
+        };
+        let path = self.dir.join(format!("{}.md", doc.id));
+        let mut file = File::create(&path)
+            .unwrap_or_else(|_| panic!("can't open file[{}] with write option", path.display()));
+        file.write_all(markdown.as_bytes())
+            .unwrap_or_else(|_| panic!("can't write document[{}]", doc.id));
+    }
+
+    fn flush(&mut self) {}
+}
@@ -0,0 +1,284 @@
+use crate::output::Output;
+use crate::parser::model::{Document, ImageType, Link, Text};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE documents (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    revision_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    redirect_to TEXT
+);
+CREATE TABLE sections (
+    id INTEGER PRIMARY KEY,
+    doc_id TEXT NOT NULL REFERENCES documents(id),
+    section_index INTEGER NOT NULL,
+    heading TEXT,
+    text TEXT NOT NULL
+);
+CREATE INDEX sections_doc_id ON sections(doc_id);
+CREATE TABLE links (
+    doc_id TEXT NOT NULL REFERENCES documents(id),
+    link_index INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    link_target TEXT NOT NULL,
+    external INTEGER NOT NULL
+);
+CREATE INDEX links_doc_id ON links(doc_id);
+CREATE INDEX links_link_target ON links(link_target);
+CREATE TABLE categories (
+    doc_id TEXT NOT NULL REFERENCES documents(id),
+    category TEXT NOT NULL
+);
+CREATE INDEX categories_doc_id ON categories(doc_id);
+CREATE INDEX categories_category ON categories(category);
+CREATE TABLE images (
+    doc_id TEXT NOT NULL REFERENCES documents(id),
+    image_index INTEGER NOT NULL,
+    target TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    text TEXT NOT NULL,
+    link_target TEXT
+);
+CREATE INDEX images_doc_id ON images(doc_id);
+CREATE VIRTUAL TABLE sections_fts USING fts5(
+    heading,
+    text,
+    content='sections',
+    content_rowid='id'
+);
+";
+
+// Write documents into normalized SQLite tables.
+// Section text is indexed by the FTS5 table `sections_fts`.
+pub struct OutputSqlite {
+    conn: Connection,
+    counter: u16,
+    page_limit: u16,
+}
+
+impl OutputSqlite {
+    // page_limit is the number of documents committed in one transaction.
+    // An existing database file at `{path}.sqlite` is replaced.
+    pub fn new(path: &str, page_limit: u16) -> Self {
+        let file_path = format!("{}.sqlite", &path);
+        if Path::new(file_path.as_str()).exists() {
+            fs::remove_file(file_path.as_str())
+                .unwrap_or_else(|_| panic!("can't remove file[{}]", file_path.as_str()));
+        }
+        let conn = Connection::open(file_path.as_str())
+            .unwrap_or_else(|_| panic!("can't open database[{}]", file_path.as_str()));
+        conn.execute_batch(SCHEMA)
+            .expect("can't create sqlite tables");
+        conn.execute_batch("BEGIN").unwrap();
+        OutputSqlite {
+            conn,
+            counter: 0,
+            page_limit,
+        }
+    }
+
+    fn insert(&self, doc: &Document) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO documents (id, title, timestamp, revision_id, url, redirect_to)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                doc.id,
+                doc.title,
+                doc.timestamp,
+                doc.revision_id,
+                doc.url,
+                doc.redirect_to
+            ],
+        )?;
+
+        // contents[0] is the lead section, contents[i] starts with headings[i - 1]
+        for (index, content) in doc.contents.iter().enumerate() {
+            let heading = if index > 0 {
+                doc.headings.get(index - 1)
+            } else {
+                None
+            };
+            // the heading is in its own column
+            let text = heading
+                .and_then(|heading| content.strip_prefix(heading.as_str()))
+                .map(|body| body.strip_prefix('\n').unwrap_or(body))
+                .unwrap_or(content);
+            self.conn.execute(
+                "INSERT INTO sections (doc_id, section_index, heading, text)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![doc.id, index as i64, heading, text],
+            )?;
+            let rowid = self.conn.last_insert_rowid();
+            self.conn.execute(
+                "INSERT INTO sections_fts (rowid, heading, text) VALUES (?1, ?2, ?3)",
+                params![rowid, heading, text],
+            )?;
+        }
+
+        for (index, link) in doc.links.iter().enumerate() {
+            let (text, link_target, external) = split_text(link);
+            if let Some(link_target) = link_target {
+                self.conn.execute(
+                    "INSERT INTO links (doc_id, link_index, text, link_target, external)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![doc.id, index as i64, text, link_target, external],
+                )?;
+            }
+        }
+
+        for category in &doc.categories {
+            self.conn.execute(
+                "INSERT INTO categories (doc_id, category) VALUES (?1, ?2)",
+                params![doc.id, category],
+            )?;
+        }
+
+        for (index, image) in doc.images.iter().enumerate() {
+            let (text, link_target, _) = split_text(&image.text);
+            let target_type = match image.target_type {
+                ImageType::Image => "Image",
+                ImageType::File => "File",
+            };
+            self.conn.execute(
+                "INSERT INTO images (doc_id, image_index, target, target_type, text, link_target)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    doc.id,
+                    index as i64,
+                    image.target,
+                    target_type,
+                    text,
+                    link_target
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// returns (text, link target, external link?)
+fn split_text(text: &Text) -> (&str, Option<&str>, bool) {
+    match text {
+        Text::LinkText {
+            text,
+            link: Link::ExternalLink { link_target },
+        } => (text, Some(link_target), true),
+        Text::LinkText {
+            text,
+            link: Link::Link { link_target },
+        } => (text, Some(link_target), false),
+        Text::Text { text } => (text, None, false),
+    }
+}
+
+impl Output for OutputSqlite {
+    fn output(&mut self, doc: &Document) {
+        self.insert(doc)
+            .unwrap_or_else(|e| panic!("can't insert document[{}]: {}", doc.id, e));
+        self.counter += 1;
+        if self.counter == self.page_limit {
+            self.conn.execute_batch("COMMIT; BEGIN").unwrap();
+            self.counter = 0;
+        }
+    }
+
+    fn flush(&mut self) {
+        self.conn.execute_batch("COMMIT; BEGIN").unwrap();
+    }
+}
+
+impl Drop for OutputSqlite {
+    fn drop(&mut self) {
+        if !self.conn.is_autocommit() {
+            let _ = self.conn.execute_batch("COMMIT");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_searchable() {
+        let path = std::env::temp_dir().join(format!("sqlite_test_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut output = OutputSqlite::new(path, 100);
+        let link = |text: &str, link_target: &str, external: bool| Text::LinkText {
+            text: text.to_string(),
+            link: if external {
+                Link::ExternalLink {
+                    link_target: link_target.to_string(),
+                }
+            } else {
+                Link::Link {
+                    link_target: link_target.to_string(),
+                }
+            },
+        };
+        output.output(&Document {
+            id: String::from("5"),
+            title: String::from("アンパサンド"),
+            contents: vec![
+                String::from("アンパサンドは記号である。"),
+                String::from("歴史\nampersand の由来。"),
+            ],
+            headings: vec![String::from("歴史")],
+            links: vec![
+                link("記号", "記号", false),
+                Text::Text {
+                    text: String::from("テキスト"),
+                },
+                link("公式", "https://example.com", true),
+            ],
+            categories: vec![String::from("約物")],
+            ..Default::default()
+        });
+        output.flush();
+
+        let conn = &output.conn;
+        let count =
+            |sql: &str| -> i64 { conn.query_row(sql, params![], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM documents"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM sections"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM links WHERE external = 1"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM links"), 2);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM categories WHERE category = '約物'"),
+            1
+        );
+        let (section_index, heading): (i64, String) = conn
+            .query_row(
+                "SELECT s.section_index, s.heading FROM sections_fts
+                 JOIN sections s ON s.id = sections_fts.rowid
+                 WHERE sections_fts MATCH 'ampersand'",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((section_index, heading.as_str()), (1, "歴史"));
+        let text: String = conn
+            .query_row(
+                "SELECT text FROM sections WHERE section_index = 1",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(text, "ampersand の由来。");
+        assert_eq!(
+            count("SELECT COUNT(*) FROM sections_fts WHERE sections_fts MATCH 'heading:歴史'"),
+            1
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM sections_fts WHERE sections_fts MATCH 'text:歴史'"),
+            0
+        );
+        drop(output);
+        fs::remove_file(format!("{}.sqlite", path)).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+use crate::output::Output;
+use crate::parser::model::Document;
+use crate::parser::sentence_parser::split_sentences;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Sections skipped by default. They hold references and links, not prose.
+pub const BOILERPLATE_SECTIONS: &[&str] = &[
+    "脚注",
+    "注釈",
+    "出典",
+    "参考文献",
+    "関連文献",
+    "関連項目",
+    "外部リンク",
+];
+
+#[derive(Clone)]
+pub struct TextSettings {
+    // write a section heading as its own paragraph
+    pub headings: bool,
+    // write list items (without "* " or "1. " markers)
+    pub lists: bool,
+    pub sentence_per_line: bool,
+    // write title and categories before the body
+    pub header: bool,
+    pub skip_sections: Vec<String>,
+}
+
+impl Default for TextSettings {
+    fn default() -> Self {
+        TextSettings {
+            headings: false,
+            lists: true,
+            sentence_per_line: false,
+            header: false,
+            skip_sections: BOILERPLATE_SECTIONS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+// Write only the body text for language model / word embedding corpora.
+// Paragraphs are separated by a blank line and documents by two blank lines.
+pub struct OutputText {
+    path: String,
+    settings: TextSettings,
+    file: BufWriter<File>,
+    counter: u16,
+    page_limit: u16,
+    file_counter: u16,
+}
+
+impl OutputText {
+    pub fn new(path: &str, settings: TextSettings, page_limit: u16) -> Self {
+        OutputText {
+            path: path.to_string(),
+            settings,
+            file: create_file(format!("{}_0.txt", &path).as_str()),
+            counter: 0,
+            page_limit,
+            file_counter: 0,
+        }
+    }
+}
+
+// Body paragraphs of the document without headings and list markers,
+// also used by output_wikiextractor
+pub fn text_paragraphs(doc: &Document, settings: &TextSettings) -> Vec<String> {
+    let mut paragraphs = vec![];
+    for (index, content) in doc.contents.iter().enumerate() {
+        // contents[0] is the lead section, contents[i] starts with headings[i - 1]
+        let (heading, body) = if index > 0 {
+            let heading = doc.headings.get(index - 1).map(|h| h.trim()).unwrap_or("");
+            if settings.skip_sections.iter().any(|s| s == heading) {
+                continue;
+            }
+            let body = content.split_once('\n').map(|(_, body)| body).unwrap_or("");
+            (heading, body)
+        } else {
+            ("", content.as_str())
+        };
+        let mut section = vec![];
+        push_paragraphs(body, settings, &mut section);
+        // a heading without text is not written
+        if settings.headings && !heading.is_empty() && !section.is_empty() {
+            paragraphs.push(heading.to_string());
+        }
+        paragraphs.extend(section);
+    }
+    paragraphs
+}
+
+// One line is one paragraph. Consecutive list items are one paragraph.
+fn push_paragraphs(body: &str, settings: &TextSettings, paragraphs: &mut Vec<String>) {
+    let mut list = vec![];
+    for line in body.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(item) = strip_list_marker(line) {
+            if settings.lists && !item.is_empty() {
+                list.push(format_paragraph(item, settings));
+            }
+            continue;
+        }
+        if !list.is_empty() {
+            paragraphs.push(list.join("\n"));
+            list.clear();
+        }
+        paragraphs.push(format_paragraph(line, settings));
+    }
+    if !list.is_empty() {
+        paragraphs.push(list.join("\n"));
+    }
+}
+
+fn format_paragraph(paragraph: &str, settings: &TextSettings) -> String {
+    if settings.sentence_per_line {
+        split_sentences(paragraph).join("\n")
+    } else {
+        paragraph.to_string()
+    }
+}
+
+// "* item" or "1. item" produced by list_parser
+fn strip_list_marker(line: &str) -> Option<&str> {
+    if let Some(item) = line.strip_prefix("* ") {
+        return Some(item.trim());
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(item) = line[digits..].strip_prefix(". ") {
+            return Some(item.trim());
+        }
+    }
+    None
+}
+
+fn create_file(path: &str) -> BufWriter<File> {
+    BufWriter::new(
+        File::create(path)
+            .unwrap_or_else(|_| panic!("can't open file[{}] with write option", path)),
+    )
+}
+
+impl Output for OutputText {
+    fn output(&mut self, doc: &Document) {
+        if doc.redirect_to.is_some() {
+            return;
+        }
+        let paragraphs = text_paragraphs(doc, &self.settings);
+        if paragraphs.is_empty() {
+            return;
+        }
+        let mut block = String::new();
+        if self.settings.header {
+            block.push_str(doc.title.as_str());
+            block.push('\n');
+            if !doc.categories.is_empty() {
+                block.push_str(doc.categories.join("\t").as_str());
+                block.push('\n');
+            }
+            block.push('\n');
+        }
+        block.push_str(paragraphs.join("\n\n").as_str());
+        writeln!(self.file, "{}\n\n", block)
+            .unwrap_or_else(|_| panic!("can't write document[{}]", doc.id));
+        self.counter += 1;
+        if self.counter == self.page_limit {
+            self.file.flush().unwrap();
+            self.file_counter += 1;
+            let path = format!("{}_{}.txt", self.path, self.file_counter);
+            self.file = create_file(path.as_str());
+            self.counter = 0;
+        }
+    }
+
+    fn flush(&mut self) {
+        self.file.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(settings: TextSettings, docs: &[Document]) -> String {
+        let path =
+            std::env::temp_dir().join(format!("text_test_{}_{}", std::process::id(), docs[0].id));
+        let path = path.to_str().unwrap();
+        let mut output = OutputText::new(path, settings, 100);
+        for doc in docs {
+            output.output(doc);
+        }
+        output.flush();
+        let file = format!("{}_0.txt", path);
+        let text = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        text
+    }
+
+    fn page(id: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            title: String::from("東京"),
+            categories: vec![String::from("日本の都市")],
+            contents: vec![
+                String::from("東京は首都。「そうか。」と言った。\n人口は多い。"),
+                String::from(
+                    "歴史\n江戸と呼ばれた。\n  * 江戸幕府\n    * 江戸城\n  1. 明治\n後の話。",
+                ),
+                String::from("脚注\n  * 出典"),
+                String::from("関連項目\n  * 大阪"),
+            ],
+            headings: vec![
+                String::from("歴史"),
+                String::from("脚注"),
+                String::from("関連項目"),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn paragraphs_and_lists() {
+        assert_eq!(
+            write(TextSettings::default(), &[page("1"), page("2")]),
+            "東京は首都。「そうか。」と言った。\n\n人口は多い。\n\n江戸と呼ばれた。\n\n\
+             江戸幕府\n江戸城\n明治\n\n後の話。\n\n\n\
+             東京は首都。「そうか。」と言った。\n\n人口は多い。\n\n江戸と呼ばれた。\n\n\
+             江戸幕府\n江戸城\n明治\n\n後の話。\n\n\n"
+        );
+    }
+
+    #[test]
+    fn sentence_per_line() {
+        let settings = TextSettings {
+            headings: true,
+            lists: false,
+            sentence_per_line: true,
+            header: true,
+            skip_sections: vec![String::from("脚注")],
+        };
+        assert_eq!(
+            write(settings, &[page("3")]),
+            "東京\n日本の都市\n\n\
+             東京は首都。\n「そうか。」と言った。\n\n人口は多い。\n\n\
+             歴史\n\n江戸と呼ばれた。\n\n後の話。\n\n\n"
+        );
+    }
+
+    #[test]
+    fn redirects_and_empty_pages() {
+        let redirect = Document {
+            redirect_to: Some(String::from("東京都")),
+            ..page("4")
+        };
+        let empty = Document {
+            contents: vec![String::new(), String::from("脚注\n  * 出典")],
+            headings: vec![String::from("脚注")],
+            ..page("5")
+        };
+        assert_eq!(write(TextSettings::default(), &[redirect, empty]), "");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::output::output_text::{text_paragraphs, TextSettings};
+use crate::output::Output;
+use crate::parser::model::Document;
+use serde_derive::Serialize;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const FILES_PER_DIR: u32 = 100;
+
+// Same fields as `WikiExtractor.py --json`
+#[derive(Serialize)]
+struct WikiExtractorJson<'a> {
+    id: &'a str,
+    revid: &'a str,
+    url: &'a str,
+    title: &'a str,
+    text: String,
+}
+
+// Write documents in the format of the Python WikiExtractor.
+// Files are `{dir}/AA/wiki_00`, `{dir}/AA/wiki_01`, ... `{dir}/AB/wiki_00`, rolled by size.
+// Redirect pages are skipped as WikiExtractor does.
+pub struct OutputWikiExtractor {
+    dir: PathBuf,
+    json: bool,
+    // body text without headings and list markers, all sections are kept
+    settings: TextSettings,
+    file: BufWriter<File>,
+    file_size: u64,
+    max_file_size: u64,
+    file_counter: u32,
+}
+
+impl OutputWikiExtractor {
+    // json: write `--json` style lines instead of `<doc>` blocks
+    pub fn new(dir: &str, json: bool, max_file_size: u64) -> Self {
+        let dir = PathBuf::from(dir);
+        OutputWikiExtractor {
+            file: create_file(&dir, 0),
+            dir,
+            json,
+            settings: TextSettings {
+                skip_sections: vec![],
+                ..TextSettings::default()
+            },
+            file_size: 0,
+            max_file_size,
+            file_counter: 0,
+        }
+    }
+
+    fn format(&self, doc: &Document) -> String {
+        // one paragraph or list item per line
+        let text = text_paragraphs(doc, &self.settings).join("\n");
+        if self.json {
+            let json = WikiExtractorJson {
+                id: doc.id.as_str(),
+                revid: doc.revision_id.as_str(),
+                url: doc.url.as_str(),
+                title: doc.title.as_str(),
+                text,
+            };
+            format!("{}\n", serde_json::to_string(&json).unwrap())
+        } else {
+            format!(
+                "<doc id=\"{}\" url=\"{}\" title=\"{}\">\n{}\n\n{}\n</doc>\n",
+                doc.id, doc.url, doc.title, doc.title, text
+            )
+        }
+    }
+}
+
+// AA/wiki_00 for 0, AA/wiki_99 for 99, AB/wiki_00 for 100 ...
+fn file_path(dir: &Path, counter: u32) -> PathBuf {
+    let dir_index = counter / FILES_PER_DIR;
+    let first = (b'A' + (dir_index / 26) as u8) as char;
+    let second = (b'A' + (dir_index % 26) as u8) as char;
+    dir.join(format!("{}{}", first, second))
+        .join(format!("wiki_{:02}", counter % FILES_PER_DIR))
+}
+
+fn create_file(dir: &Path, counter: u32) -> BufWriter<File> {
+    let path = file_path(dir, counter);
+    fs::create_dir_all(path.parent().unwrap())
+        .unwrap_or_else(|_| panic!("can't create directory[{}]", path.display()));
+    BufWriter::new(
+        File::create(&path)
+            .unwrap_or_else(|_| panic!("can't open file[{}] with write option", path.display())),
+    )
+}
+
+impl Output for OutputWikiExtractor {
+    fn output(&mut self, doc: &Document) {
+        if doc.redirect_to.is_some() {
+            return;
+        }
+        let str = self.format(doc);
+        if self.file_size > 0 && self.file_size + str.len() as u64 > self.max_file_size {
+            self.file.flush().unwrap();
+            self.file_counter += 1;
+            self.file = create_file(&self.dir, self.file_counter);
+            self.file_size = 0;
+        }
+        self.file
+            .write_all(str.as_bytes())
+            .unwrap_or_else(|_| panic!("can't write document[{}]", doc.id));
+        self.file_size += str.len() as u64;
+    }
+
+    fn flush(&mut self) {
+        self.file.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "wikiextractor_test_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn page(id: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            revision_id: String::from("10"),
+            url: format!("https://ja.wikipedia.org/wiki?curid={}", id),
+            title: String::from("東京"),
+            contents: vec![
+                String::from("東京は首都。\n人口は多い。"),
+                String::from("歴史\n江戸と呼ばれた。\n  * 江戸幕府\n    * 江戸城\n  1. 明治"),
+            ],
+            headings: vec![String::from("歴史")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn file_paths() {
+        let dir = Path::new("out");
+        assert_eq!(file_path(dir, 0), dir.join("AA").join("wiki_00"));
+        assert_eq!(file_path(dir, 99), dir.join("AA").join("wiki_99"));
+        assert_eq!(file_path(dir, 100), dir.join("AB").join("wiki_00"));
+        assert_eq!(file_path(dir, 2600), dir.join("BA").join("wiki_00"));
+    }
+
+    #[test]
+    fn doc_format() {
+        let dir = temp_dir("doc");
+        let mut output = OutputWikiExtractor::new(dir.to_str().unwrap(), false, 1024 * 1024);
+        let redirect = Document {
+            redirect_to: Some(String::from("東京都")),
+            ..page("2")
+        };
+        output.output(&page("1"));
+        output.output(&redirect);
+        output.flush();
+        let text = fs::read_to_string(dir.join("AA").join("wiki_00")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            text,
+            "<doc id=\"1\" url=\"https://ja.wikipedia.org/wiki?curid=1\" title=\"東京\">\n\
+             東京\n\n\
+             東京は首都。\n人口は多い。\n江戸と呼ばれた。\n江戸幕府\n江戸城\n明治\n\
+             </doc>\n"
+        );
+    }
+
+    #[test]
+    fn json_format_and_rollover() {
+        let dir = temp_dir("json");
+        let mut output = OutputWikiExtractor::new(dir.to_str().unwrap(), true, 100);
+        output.output(&page("1"));
+        output.output(&page("2"));
+        output.flush();
+        let first = fs::read_to_string(dir.join("AA").join("wiki_00")).unwrap();
+        let second = fs::read_to_string(dir.join("AA").join("wiki_01")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let json: serde_json::Value = serde_json::from_str(first.trim_end()).unwrap();
+        assert_eq!(json["id"], "1");
+        assert_eq!(json["revid"], "10");
+        assert_eq!(json["title"], "東京");
+        assert_eq!(
+            json["text"],
+            "東京は首都。\n人口は多い。\n江戸と呼ばれた。\n江戸幕府\n江戸城\n明治"
+        );
+        assert_eq!(first.lines().count(), 1);
+        assert!(second.contains("\"id\":\"2\""));
+    }
+}
@@ -1,4 +1,21 @@
+pub mod annotation_parser;
+pub mod authority_parser;
+pub mod citation_parser;
 pub mod common_parser;
+pub mod coordinate_parser;
+pub mod date_parser;
+pub mod era_parser;
+pub mod identifier_parser;
+pub mod infobox_parser;
 pub mod list_parser;
+pub mod markdown_parser;
 pub mod model;
+pub mod parser_function;
+pub mod passage_parser;
+pub mod provenance_parser;
+pub mod reading_parser;
+pub mod reference_parser;
+pub mod sentence_parser;
+pub mod table_parser;
+pub mod template_expander;
 pub mod template_parser;
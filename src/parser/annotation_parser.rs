@@ -0,0 +1,538 @@
+use crate::parser::common_parser::parse_text_only;
+use crate::parser::model::{Annotation, AnnotationType, Document, Link, Text};
+use crate::parser::passage_parser::SectionInfo;
+use crate::parser::reading_parser::push_reading;
+use crate::parser::template_parser::{
+    get_param, parse_template, parse_template_rule, TemplateContext, TemplateRule,
+};
+use log::{debug, warn};
+use parse_wiki_text::{Node, Parameter};
+use std::str::FromStr;
+
+// Noncharacters wrap annotated spans while a page is extracted.
+// strip_annotations removes them and turns them into offsets.
+// Unlike private use characters, which jawiki uses for gaiji, noncharacters are not
+// used in text. Those in the input are replaced by replace_markers.
+pub const LINK_START: char = '\u{FDD0}';
+pub const LINK_END: char = '\u{FDD1}';
+pub const BOLD: char = '\u{FDD2}';
+pub const ITALIC: char = '\u{FDD3}';
+pub const LANG_START: char = '\u{FDD4}';
+pub const LANG_END: char = '\u{FDD5}';
+// between the language code and the text of a lang span
+pub const LANG_SEPARATOR: char = '\u{FDD6}';
+// position of a <ref> tag
+pub const FOOTNOTE: char = '\u{FDD7}';
+// <ruby> and </ruby>
+pub const RUBY_START: char = '\u{FDD8}';
+pub const RUBY_END: char = '\u{FDD9}';
+// <rt> and </rt>, the reading between them is removed from the text
+pub const RUBY_TEXT: char = '\u{FDDA}';
+// <rp> and </rp>, the parentheses between them are removed
+pub const RUBY_PARENTHESIS: char = '\u{FDDB}';
+
+const MARKERS: &[char] = &[
+    LINK_START,
+    LINK_END,
+    BOLD,
+    ITALIC,
+    LANG_START,
+    LANG_END,
+    LANG_SEPARATOR,
+    FOOTNOTE,
+    RUBY_START,
+    RUBY_END,
+    RUBY_TEXT,
+    RUBY_PARENTHESIS,
+];
+
+// Replace noncharacters in the wikitext with U+FFFD before extraction, so that they
+// are not taken for markers. Both are 3 bytes in UTF-8 and byte offsets do not change.
+pub fn replace_markers(wikitext: String) -> String {
+    let is_noncharacter = |c: char| ('\u{FDD0}'..='\u{FDEF}').contains(&c);
+    if wikitext.contains(is_noncharacter) {
+        wikitext.replace(is_noncharacter, "\u{FFFD}")
+    } else {
+        wikitext
+    }
+}
+
+impl FromStr for AnnotationType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "link" => Ok(AnnotationType::Link),
+            "external_link" => Ok(AnnotationType::ExternalLink),
+            "bold" => Ok(AnnotationType::Bold),
+            "italic" => Ok(AnnotationType::Italic),
+            "lang" => Ok(AnnotationType::Lang),
+            "era" => Ok(AnnotationType::Era),
+            "ruby" => Ok(AnnotationType::Ruby),
+            _ => {
+                warn!("Unexpected Annotation Type: [{}]", s);
+                Err(())
+            }
+        }
+    }
+}
+
+// Append link text wrapped in link markers and add the link to doc.links.
+pub fn push_link_text(content: &mut String, link_text: Text, doc: &mut Document) {
+    content.push(LINK_START);
+    content.push_str(link_text.clone_text().as_str());
+    content.push(LINK_END);
+    doc.links.push(link_text);
+}
+
+// Append the text of a template. Text of lang templates is wrapped in lang markers.
+pub fn push_template_text(
+    context: &TemplateContext,
+    content: &mut String,
+    name: &[Node],
+    parameters: &[Parameter],
+) {
+    let text = match parse_template(context, name, parameters) {
+        Some(text) => text,
+        None => return,
+    };
+    let rule = parse_template_rule(context.rules, name);
+    if let Some(TemplateRule::Ruby { parentheses }) = rule {
+        // {{ルビ|漢字|かんじ}} is the same as <ruby>漢字<rt>かんじ</rt></ruby>
+        let reading = get_param(parameters, 2).unwrap_or_default();
+        content.push(RUBY_START);
+        content.push_str(get_param(parameters, 1).unwrap_or_default().as_str());
+        content.push(RUBY_TEXT);
+        content.push_str(reading.as_str());
+        content.push(RUBY_TEXT);
+        content.push(RUBY_END);
+        if *parentheses && !reading.is_empty() {
+            content.push_str(format!("（{}）", reading).as_str());
+        }
+    } else if let Some(TemplateRule::Lang) = rule {
+        // {{lang|en|text}} or {{lang-en|text}}
+        let template_name = parse_text_only(name);
+        let code = match template_name.trim().split_once('-').map(|(_, code)| code) {
+            Some(code) => code.to_string(),
+            None if parameters.len() >= 2 => parse_text_only(&parameters[0].value),
+            None => String::new(),
+        };
+        content.push(LANG_START);
+        content.push_str(code.trim());
+        content.push(LANG_SEPARATOR);
+        content.push_str(text.as_str());
+        content.push(LANG_END);
+    } else {
+        content.push_str(text.as_str());
+    }
+}
+
+// Append footnote markers of <ref> tags in a template or a table, see nested_refs
+pub fn push_footnotes(content: &mut String, count: usize) {
+    for _ in 0..count {
+        content.push(FOOTNOTE);
+    }
+}
+
+// Append the marker of a ruby tag, other tags are not written to the text
+pub fn push_tag(content: &mut String, name: &str) {
+    match name.to_lowercase().as_str() {
+        "ruby" => content.push(RUBY_START),
+        "rt" => content.push(RUBY_TEXT),
+        "rp" => content.push(RUBY_PARENTHESIS),
+        _ => {}
+    }
+}
+
+pub fn push_end_tag(content: &mut String, name: &str) {
+    match name.to_lowercase().as_str() {
+        "ruby" => content.push(RUBY_END),
+        "rt" => content.push(RUBY_TEXT),
+        "rp" => content.push(RUBY_PARENTHESIS),
+        _ => {}
+    }
+}
+
+// Remove markers from doc.contents and return the annotations of the given types.
+// Offsets are character offsets in doc.contents[section_index].
+// Links are matched with doc.links in order and footnote markers with doc.footnotes.
+// Readings of ruby are added to doc.readings.
+pub fn strip_annotations(
+    doc: &mut Document,
+    sections: &mut [SectionInfo],
+    types: &[AnnotationType],
+) -> Vec<Annotation> {
+    let mut annotations = vec![];
+    let mut link_index = 0;
+    let mut footnote_index = 0;
+    for (section_index, content) in doc.contents.iter_mut().enumerate() {
+        if !content.contains(MARKERS) {
+            continue;
+        }
+        let mut text = String::with_capacity(content.len());
+        let mut chars = 0;
+        // start offsets of open spans
+        let mut links: Vec<usize> = vec![];
+        let mut langs: Vec<(usize, String)> = vec![];
+        // in the language code of a lang span
+        let mut lang_code = false;
+        let mut bold: Option<usize> = None;
+        let mut italic: Option<usize> = None;
+        // start offset, base text and reading of the open ruby
+        let mut ruby: Option<(usize, String, String)> = None;
+        let mut ruby_text = false;
+        let mut ruby_parenthesis = false;
+        // byte offset in the stripped text for each byte offset in the content
+        let mut positions = Vec::with_capacity(content.len() + 1);
+
+        for c in content.chars() {
+            positions.resize(positions.len() + c.len_utf8(), text.len());
+            match c {
+                LINK_START => links.push(chars),
+                LINK_END => {
+                    if let Some(start) = links.pop() {
+                        let (annotation_type, target) = match doc.links.get(link_index) {
+                            Some(Text::LinkText {
+                                link: Link::ExternalLink { link_target },
+                                ..
+                            }) => (AnnotationType::ExternalLink, Some(link_target.to_string())),
+                            Some(Text::LinkText {
+                                link: Link::Link { link_target },
+                                ..
+                            }) => (AnnotationType::Link, Some(link_target.to_string())),
+                            _ => (AnnotationType::Link, None),
+                        };
+                        link_index += 1;
+                        push(
+                            &mut annotations,
+                            types,
+                            annotation_type,
+                            section_index,
+                            start,
+                            chars,
+                            target,
+                        );
+                    }
+                }
+                FOOTNOTE => {
+                    if let Some(footnote) = doc.footnotes.get_mut(footnote_index) {
+                        footnote.section_index = section_index;
+                        footnote.offset = chars;
+                    }
+                    footnote_index += 1;
+                }
+                LANG_START => {
+                    langs.push((chars, String::new()));
+                    lang_code = true;
+                }
+                LANG_SEPARATOR => lang_code = false,
+                LANG_END => {
+                    if let Some((start, code)) = langs.pop() {
+                        let target = if code.is_empty() { None } else { Some(code) };
+                        push(
+                            &mut annotations,
+                            types,
+                            AnnotationType::Lang,
+                            section_index,
+                            start,
+                            chars,
+                            target,
+                        );
+                    }
+                }
+                RUBY_START => {
+                    ruby = Some((chars, String::new(), String::new()));
+                    ruby_text = false;
+                    ruby_parenthesis = false;
+                }
+                RUBY_TEXT => ruby_text = ruby.is_some() && !ruby_text,
+                RUBY_PARENTHESIS => ruby_parenthesis = ruby.is_some() && !ruby_parenthesis,
+                RUBY_END => {
+                    if let Some((start, base, reading)) = ruby.take() {
+                        push_reading(&mut doc.readings, base.as_str(), reading.as_str(), "ruby");
+                        let reading = reading.trim();
+                        let target = if reading.is_empty() {
+                            None
+                        } else {
+                            Some(reading.to_string())
+                        };
+                        push(
+                            &mut annotations,
+                            types,
+                            AnnotationType::Ruby,
+                            section_index,
+                            start,
+                            chars,
+                            target,
+                        );
+                    }
+                    ruby_text = false;
+                    ruby_parenthesis = false;
+                }
+                _ if ruby_parenthesis && !MARKERS.contains(&c) => {}
+                _ if ruby_text && !MARKERS.contains(&c) => {
+                    if let Some((_, _, reading)) = ruby.as_mut() {
+                        reading.push(c);
+                    }
+                }
+                _ if lang_code => {
+                    if let Some((_, code)) = langs.last_mut() {
+                        code.push(c);
+                    }
+                }
+                BOLD => match bold.take() {
+                    Some(start) => push(
+                        &mut annotations,
+                        types,
+                        AnnotationType::Bold,
+                        section_index,
+                        start,
+                        chars,
+                        None,
+                    ),
+                    None => bold = Some(chars),
+                },
+                ITALIC => match italic.take() {
+                    Some(start) => push(
+                        &mut annotations,
+                        types,
+                        AnnotationType::Italic,
+                        section_index,
+                        start,
+                        chars,
+                        None,
+                    ),
+                    None => italic = Some(chars),
+                },
+                _ => {
+                    // '' and ''' are closed at the end of the line
+                    if c == '\n' {
+                        if let Some(start) = bold.take() {
+                            push(
+                                &mut annotations,
+                                types,
+                                AnnotationType::Bold,
+                                section_index,
+                                start,
+                                chars,
+                                None,
+                            );
+                        }
+                        if let Some(start) = italic.take() {
+                            push(
+                                &mut annotations,
+                                types,
+                                AnnotationType::Italic,
+                                section_index,
+                                start,
+                                chars,
+                                None,
+                            );
+                        }
+                    }
+                    if let Some((_, base, _)) = ruby.as_mut() {
+                        base.push(c);
+                    }
+                    text.push(c);
+                    chars += 1;
+                }
+            }
+        }
+        positions.push(text.len());
+        // paragraph breaks and segments move with the removed markers
+        if let Some(section) = sections.get_mut(section_index) {
+            let position = |offset: usize| positions[offset.min(positions.len() - 1)];
+            for paragraph_break in section.paragraph_breaks.iter_mut() {
+                *paragraph_break = position(*paragraph_break);
+            }
+            for segment in section.segments.iter_mut() {
+                segment.start = position(segment.start);
+                segment.end = position(segment.end);
+            }
+        }
+        for (annotation_type, start) in [
+            (AnnotationType::Bold, bold),
+            (AnnotationType::Italic, italic),
+        ] {
+            if let Some(start) = start {
+                push(
+                    &mut annotations,
+                    types,
+                    annotation_type,
+                    section_index,
+                    start,
+                    chars,
+                    None,
+                );
+            }
+        }
+        if !links.is_empty() || !langs.is_empty() || ruby.is_some() {
+            debug!(
+                "[WARN] unclosed annotation in section[{}] of [{}]",
+                section_index, doc.title
+            );
+        }
+        *content = text;
+    }
+    doc.footnotes.truncate(footnote_index);
+    annotations.sort_by_key(|a| (a.section_index, a.start, a.end));
+    annotations
+}
+
+fn push(
+    annotations: &mut Vec<Annotation>,
+    types: &[AnnotationType],
+    annotation_type: AnnotationType,
+    section_index: usize,
+    start: usize,
+    end: usize,
+    target: Option<String>,
+) {
+    if start < end && types.contains(&annotation_type) {
+        annotations.push(Annotation {
+            annotation_type,
+            section_index,
+            start,
+            end,
+            target,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::model::Footnote;
+
+    const ALL: &[AnnotationType] = &[
+        AnnotationType::Link,
+        AnnotationType::ExternalLink,
+        AnnotationType::Bold,
+        AnnotationType::Italic,
+        AnnotationType::Lang,
+        AnnotationType::Ruby,
+    ];
+
+    fn document(contents: &[String]) -> (Document, Vec<SectionInfo>) {
+        let doc = Document {
+            contents: contents.to_vec(),
+            ..Default::default()
+        };
+        let sections = contents.iter().map(|_| SectionInfo::new(0, &doc)).collect();
+        (doc, sections)
+    }
+
+    fn spans(
+        annotations: &[Annotation],
+    ) -> Vec<(AnnotationType, usize, usize, usize, Option<&str>)> {
+        annotations
+            .iter()
+            .map(|a| {
+                (
+                    a.annotation_type,
+                    a.section_index,
+                    a.start,
+                    a.end,
+                    a.target.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn character_offsets() {
+        let mut doc = Document::default();
+        let mut content = String::from("日本の");
+        let link = Link::Link {
+            link_target: String::from("首都"),
+        };
+        let text = String::from("首都");
+        push_link_text(&mut content, Text::LinkText { text, link }, &mut doc);
+        content.push_str(&format!("は{}東京{}。", BOLD, BOLD));
+        doc.contents = vec![String::from("リード"), content];
+        let mut sections = vec![SectionInfo::new(0, &doc), SectionInfo::new(2, &doc)];
+        let annotations = strip_annotations(&mut doc, &mut sections, ALL);
+        assert_eq!(doc.contents[1], "日本の首都は東京。");
+        assert_eq!(
+            spans(&annotations),
+            vec![
+                (AnnotationType::Link, 1, 3, 5, Some("首都")),
+                (AnnotationType::Bold, 1, 6, 8, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn lang_and_ruby() {
+        let content = format!(
+            "{}en{}Tokyo{}と{}東京{}とうきょう{}{}",
+            LANG_START, LANG_SEPARATOR, LANG_END, RUBY_START, RUBY_TEXT, RUBY_TEXT, RUBY_END
+        );
+        let (mut doc, mut sections) = document(&[content]);
+        let annotations = strip_annotations(&mut doc, &mut sections, ALL);
+        assert_eq!(doc.contents[0], "Tokyoと東京");
+        assert_eq!(
+            spans(&annotations),
+            vec![
+                (AnnotationType::Lang, 0, 0, 5, Some("en")),
+                (AnnotationType::Ruby, 0, 6, 8, Some("とうきょう")),
+            ]
+        );
+        assert_eq!(doc.readings[0].text, "東京");
+        assert_eq!(doc.readings[0].katakana, "トウキョウ");
+    }
+
+    #[test]
+    fn unclosed_emphasis() {
+        let content = format!("{}太字\n{}斜体", BOLD, ITALIC);
+        let (mut doc, mut sections) = document(&[content]);
+        let annotations = strip_annotations(&mut doc, &mut sections, ALL);
+        assert_eq!(
+            spans(&annotations),
+            vec![
+                (AnnotationType::Bold, 0, 0, 2, None),
+                (AnnotationType::Italic, 0, 3, 5, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn footnotes_and_paragraph_breaks() {
+        let content = format!("一文目。{}{}\n二文目。", FOOTNOTE, FOOTNOTE);
+        let paragraph_break = content.find('\n').unwrap();
+        let (mut doc, mut sections) = document(&[content]);
+        sections[0].paragraph_breaks.push(paragraph_break);
+        for reference_index in 0..3 {
+            doc.footnotes.push(Footnote {
+                reference_index,
+                section_index: 0,
+                offset: 0,
+            });
+        }
+        strip_annotations(&mut doc, &mut sections, ALL);
+        assert_eq!(doc.contents[0], "一文目。\n二文目。");
+        let offsets: Vec<usize> = doc.footnotes.iter().map(|f| f.offset).collect();
+        // footnotes without a marker are removed
+        assert_eq!(offsets, vec![4, 4]);
+        assert_eq!(sections[0].paragraph_breaks, vec!["一文目。".len()]);
+    }
+
+    #[test]
+    fn types_are_filtered() {
+        let content = format!("{}太字{}", BOLD, BOLD);
+        let (mut doc, mut sections) = document(&[content]);
+        let annotations = strip_annotations(&mut doc, &mut sections, &[AnnotationType::Link]);
+        assert_eq!(doc.contents[0], "太字");
+        assert!(annotations.is_empty());
+    }
+
+    #[test]
+    fn noncharacters_in_wikitext() {
+        let wikitext = format!("a{}b", BOLD);
+        let replaced = replace_markers(wikitext.clone());
+        assert_eq!(replaced, "a\u{FFFD}b");
+        assert_eq!(replaced.len(), wikitext.len());
+        // gaiji in the private use area are kept
+        assert_eq!(replace_markers(String::from("\u{E000}")), "\u{E000}");
+    }
+}
@@ -0,0 +1,89 @@
+use crate::parser::common_parser::parse_text_only;
+use crate::parser::model::AuthorityId;
+use parse_wiki_text::Parameter;
+
+// parameters of {{Normdaten}} that are not ids
+const SKIP_PARAMETERS: &[&str] = &["TYP", "STATE", "SHOW", "LISTCLASS"];
+
+// {{Normdaten|VIAF=123456|NDL=00562345}} or {{典拠管理}}.
+// Parameter names are the database names such as VIAF, NDL, LCCN, GND and ISNI.
+// The template also shows the ids of the Wikidata item, so a Wikidata entry is
+// added with the QID given to the template or without an id for the item of the page.
+pub fn parse_authority_control(parameters: &[Parameter]) -> Vec<AuthorityId> {
+    let mut ids = vec![];
+    let mut wikidata = None;
+    for parameter in parameters {
+        let name = match &parameter.name {
+            Some(name) => parse_text_only(name).trim().to_uppercase(),
+            None => continue,
+        };
+        let value = parse_text_only(&parameter.value).trim().to_string();
+        if value.is_empty() || SKIP_PARAMETERS.contains(&name.as_str()) {
+            continue;
+        }
+        // VIAF2, VIAF3, ... are more ids of VIAF
+        let source = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        let source = if source.is_empty() {
+            name.as_str()
+        } else {
+            source
+        };
+        match source {
+            "QID" | "WIKIDATA" => wikidata = Some(value),
+            // written with spaces as 0000 0001 2103 2683
+            "ISNI" => ids.push(AuthorityId {
+                source: source.to_string(),
+                id: Some(value.split_whitespace().collect()),
+            }),
+            _ => ids.push(AuthorityId {
+                source: source.to_string(),
+                id: Some(value),
+            }),
+        }
+    }
+    ids.push(AuthorityId {
+        source: String::from("WIKIDATA"),
+        id: wikidata,
+    });
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+    use parse_wiki_text::Node;
+
+    fn ids(wikitext: &str) -> Vec<(String, Option<String>)> {
+        let result = create_ja_config().parse(wikitext);
+        match result.nodes.first() {
+            Some(Node::Template { parameters, .. }) => parse_authority_control(parameters)
+                .into_iter()
+                .map(|id| (id.source, id.id))
+                .collect(),
+            _ => panic!("no template in [{}]", wikitext),
+        }
+    }
+
+    #[test]
+    fn normdaten() {
+        assert_eq!(
+            ids("{{Normdaten|TYP=p|VIAF=123456|viaf2=789|NDL=00562345|ISNI=0000 0001 2103 2683|LCCN=}}"),
+            vec![
+                (String::from("VIAF"), Some(String::from("123456"))),
+                (String::from("VIAF"), Some(String::from("789"))),
+                (String::from("NDL"), Some(String::from("00562345"))),
+                (String::from("ISNI"), Some(String::from("0000000121032683"))),
+                (String::from("WIKIDATA"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn wikidata() {
+        assert_eq!(
+            ids("{{典拠管理|QID=Q1490}}"),
+            vec![(String::from("WIKIDATA"), Some(String::from("Q1490")))]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::parser::common_parser::parse_text_only;
+use crate::parser::model::Citation;
+use parse_wiki_text::{Node, Parameter};
+use std::collections::HashMap;
+
+// Cite web, Cite book, Cite journal, Cite news, ... and Citation
+pub fn is_citation(name: &str) -> bool {
+    let name = name.trim().to_lowercase();
+    name.starts_with("cite") || name == "citation"
+}
+
+// Parameter names of the fields, the first one written is used.
+// reference_index and section_index are set by the caller.
+pub fn parse_citation(name: &[Node], parameters: &[Parameter]) -> Citation {
+    let mut values = HashMap::new();
+    // {{Cite web|和書|...}} of jawiki is a source in Japanese
+    let mut japanese = false;
+    for parameter in parameters {
+        let value = parse_text_only(&parameter.value).trim().to_string();
+        match &parameter.name {
+            Some(name) if !value.is_empty() => {
+                values.insert(parse_text_only(name).trim().to_lowercase(), value);
+            }
+            Some(_) => {}
+            None => japanese = japanese || value == "和書",
+        }
+    }
+    let get = |names: &[&str]| names.iter().find_map(|name| values.get(*name).cloned());
+    Citation {
+        citation_type: parse_text_only(name).trim().to_string(),
+        reference_index: None,
+        section_index: 0,
+        title: get(&["title"]),
+        authors: parse_authors(&values),
+        date: get(&["date", "year"]),
+        publisher: get(&["publisher"]),
+        url: get(&["url"]),
+        isbn: get(&["isbn", "isbn13"]),
+        doi: get(&["doi"]),
+        access_date: get(&["access-date", "accessdate"]),
+        language: get(&["language", "lang"]).or_else(|| {
+            if japanese {
+                Some(String::from("ja"))
+            } else {
+                None
+            }
+        }),
+    }
+}
+
+// author, author1, author2, ... or last1 and first1, ... then authors and coauthors.
+// Numbers stop at the first missing one.
+fn parse_authors(values: &HashMap<String, String>) -> Vec<String> {
+    let mut authors: Vec<String> = vec![];
+    for index in 0.. {
+        let suffix = if index == 0 {
+            String::new()
+        } else {
+            index.to_string()
+        };
+        let get = |name: &str| values.get(format!("{}{}", name, suffix).as_str());
+        let author = match (get("author"), get("last")) {
+            (Some(author), _) => Some(author.to_string()),
+            (None, Some(last)) => Some(match get("first") {
+                Some(first) => format!("{}, {}", last, first),
+                None => last.to_string(),
+            }),
+            (None, None) => None,
+        };
+        match author {
+            // author and author1 are the same author
+            Some(author) if !authors.contains(&author) => authors.push(author),
+            Some(_) => {}
+            None if index > 0 => break,
+            None => {}
+        }
+    }
+    for name in ["authors", "coauthors"] {
+        if let Some(author) = values.get(name) {
+            authors.push(author.to_string());
+        }
+    }
+    authors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+
+    fn cite(wikitext: &str) -> Citation {
+        let result = create_ja_config().parse(wikitext);
+        match result.nodes.first() {
+            Some(Node::Template {
+                name, parameters, ..
+            }) => parse_citation(name, parameters),
+            _ => panic!("no template in [{}]", wikitext),
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert!(is_citation("Cite web"));
+        assert!(is_citation(" cite journal "));
+        assert!(is_citation("Citation"));
+        assert!(!is_citation("Citation needed"));
+        assert!(!is_citation("Infobox"));
+    }
+
+    #[test]
+    fn fields() {
+        let citation = cite(
+            "{{Cite web|和書|url=https://example.com|title=記号の歴史|year=2001\
+             |accessdate=2019-01-01|publisher=出版社|isbn13=978-4-10-109205-8|doi=}}",
+        );
+        assert_eq!(citation.citation_type, "Cite web");
+        assert_eq!(citation.title.as_deref(), Some("記号の歴史"));
+        assert_eq!(citation.date.as_deref(), Some("2001"));
+        assert_eq!(citation.access_date.as_deref(), Some("2019-01-01"));
+        assert_eq!(citation.isbn.as_deref(), Some("978-4-10-109205-8"));
+        assert_eq!(citation.doi, None);
+        assert_eq!(citation.language.as_deref(), Some("ja"));
+        let citation = cite("{{Cite book|title=Book|language=en|date=2000-01-02|year=2000}}");
+        assert_eq!(citation.date.as_deref(), Some("2000-01-02"));
+        assert_eq!(citation.language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn authors() {
+        let citation = cite(
+            "{{Cite book|last1=Smith|first1=John|last2=Doe|author3=山田太郎|last5=Skipped|coauthors=他}}",
+        );
+        assert_eq!(
+            citation.authors,
+            vec!["Smith, John", "Doe", "山田太郎", "他"]
+        );
+        let citation = cite("{{Cite book|author=山田太郎|author1=山田太郎|author2=鈴木花子}}");
+        assert_eq!(citation.authors, vec!["山田太郎", "鈴木花子"]);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::parser::model::{Image, ImageType, Link, Text};
-use crate::parser::template_parser::parse_template;
-use log::debug;
+use crate::parser::template_parser::{parse_template, TemplateContext};
+use log::{debug, trace};
 use parse_wiki_text::Node;
 use std::str::FromStr;
 
@@ -18,7 +18,7 @@ pub fn extract_heading_text(nodes: &Vec<Node>) -> String {
 }
 
 // only return text.
-pub fn parse_text_only(nodes: &Vec<Node>) -> String {
+pub fn parse_text_only(nodes: &[Node]) -> String {
     let mut str = String::new();
     for node in nodes {
         match node {
@@ -83,9 +83,9 @@ pub fn extract_link_text(target: &str, nodes: &Vec<Node>) -> Text {
 }
 
 // parse external link text
-pub fn extract_external_link_text(nodes: &Vec<Node>) -> Text {
+pub fn extract_external_link_text(context: &TemplateContext, nodes: &Vec<Node>) -> Text {
     let text = if nodes.len() > 1 {
-        extract_text_only_with_lang_template(nodes)
+        extract_text_only_with_lang_template(context, nodes)
     } else {
         parse_text_only(nodes)
     };
@@ -109,7 +109,10 @@ pub fn extract_external_link_text(nodes: &Vec<Node>) -> Text {
 }
 
 //
-pub fn extract_text_only_with_lang_template(nodes: &Vec<Node>) -> String {
+pub fn extract_text_only_with_lang_template(
+    context: &TemplateContext,
+    nodes: &Vec<Node>,
+) -> String {
     debug!("[WARN] unexpected external_link nodes[{:?}]", nodes);
     let mut str = String::new();
     for node in nodes {
@@ -119,7 +122,7 @@ pub fn extract_text_only_with_lang_template(nodes: &Vec<Node>) -> String {
             Node::Template {
                 name, parameters, ..
             } => {
-                if let Some(template) = parse_template(&name, &parameters) {
+                if let Some(template) = parse_template(context, &name, &parameters) {
                     str.push_str(template.as_str());
                 }
             }
@@ -128,3 +131,127 @@ pub fn extract_text_only_with_lang_template(nodes: &Vec<Node>) -> String {
     }
     return str;
 }
+
+// Text of inline nodes and the links in it. Lists are written on one line.
+// Readings of <ruby> are not written.
+pub fn parse_inline_text(
+    context: &TemplateContext,
+    nodes: &[Node],
+    text: &mut String,
+    links: &mut Vec<Text>,
+) {
+    // in <rt> or <rp>
+    let mut ruby_text = false;
+    for node in nodes {
+        match node {
+            Node::Text { .. } if ruby_text => {}
+            Node::Text { value, .. } => text.push_str(value),
+            Node::CharacterEntity { character, .. } => text.push(*character),
+            Node::Link {
+                target,
+                text: link_text,
+                ..
+            } => {
+                let link_text = extract_link_text(target, link_text);
+                text.push_str(link_text.clone_text().as_str());
+                links.push(link_text);
+            }
+            Node::ExternalLink { nodes, .. } => {
+                let link_text = extract_external_link_text(context, nodes);
+                text.push_str(link_text.clone_text().as_str());
+                links.push(link_text);
+            }
+            Node::Template {
+                name, parameters, ..
+            } => {
+                if let Some(template) = parse_template(context, name, parameters) {
+                    text.push_str(template.as_str());
+                }
+            }
+            Node::StartTag { name, .. } if name == "br" => text.push(' '),
+            Node::StartTag { name, .. } if name == "rt" || name == "rp" => ruby_text = true,
+            Node::EndTag { name, .. } if name == "rt" || name == "rp" || name == "ruby" => {
+                ruby_text = false
+            }
+            Node::UnorderedList { items, .. } | Node::OrderedList { items, .. } => {
+                for item in items {
+                    text.push(' ');
+                    parse_inline_text(context, &item.nodes, text, links);
+                }
+            }
+            Node::ParagraphBreak { .. } => text.push(' '),
+            //NO-OP
+            Node::Bold { .. } | Node::BoldItalic { .. } | Node::Italic { .. } => {}
+            Node::StartTag { .. } | Node::EndTag { .. } | Node::Comment { .. } => {}
+            _ => {
+                trace!("い    {:?}", node);
+            }
+        }
+    }
+}
+
+// Value of an HTML attribute such as name="a", name='a' or name=a
+pub fn parse_attribute(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    while let Some(index) = rest.find('=') {
+        let key = rest[..index].split_whitespace().last().unwrap_or("");
+        let value = rest[index + 1..].trim_start();
+        let (value, next) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+                Some(end) => (&value[1..end + 1], &value[end + 2..]),
+                None => (&value[1..], ""),
+            },
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value.trim_end_matches('/').trim().to_string());
+        }
+        rest = next;
+    }
+    None
+}
+
+// Call visitor for the nodes and all nodes in them, including template parameters,
+// list items and table cells.
+pub fn visit_nodes<'a>(nodes: &'a [Node<'a>], visitor: &mut dyn FnMut(&'a Node<'a>)) {
+    for node in nodes {
+        visitor(node);
+        match node {
+            Node::Template { parameters, .. } => {
+                for parameter in parameters {
+                    visit_nodes(&parameter.value, visitor);
+                }
+            }
+            Node::Tag { nodes, .. }
+            | Node::Heading { nodes, .. }
+            | Node::Preformatted { nodes, .. }
+            | Node::ExternalLink { nodes, .. }
+            | Node::Link { text: nodes, .. }
+            | Node::Image { text: nodes, .. } => visit_nodes(nodes, visitor),
+            Node::UnorderedList { items, .. } | Node::OrderedList { items, .. } => {
+                for item in items {
+                    visit_nodes(&item.nodes, visitor);
+                }
+            }
+            Node::DefinitionList { items, .. } => {
+                for item in items {
+                    visit_nodes(&item.nodes, visitor);
+                }
+            }
+            Node::Table { captions, rows, .. } => {
+                for caption in captions {
+                    visit_nodes(&caption.content, visitor);
+                }
+                for row in rows {
+                    for cell in &row.cells {
+                        visit_nodes(&cell.content, visitor);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
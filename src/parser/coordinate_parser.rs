@@ -0,0 +1,249 @@
+use crate::parser::common_parser::{parse_text_only, visit_nodes};
+use crate::parser::model::Coordinate;
+use crate::parser::template_parser::{TemplateRule, TemplateRules};
+use log::debug;
+use parse_wiki_text::{Node, Parameter};
+use std::collections::HashMap;
+
+// Infobox parameters of degrees, minutes, seconds and N/S for the latitude, then for the longitude
+const INFOBOX_DMS: &[[&str; 8]] = &[
+    [
+        "緯度度",
+        "緯度分",
+        "緯度秒",
+        "N(北緯)及びS(南緯)",
+        "経度度",
+        "経度分",
+        "経度秒",
+        "E(東経)及びW(西経)",
+    ],
+    [
+        "latd", "latm", "lats", "latNS", "longd", "longm", "longs", "longEW",
+    ],
+    [
+        "lat_deg", "lat_min", "lat_sec", "lat_dir", "lon_deg", "lon_min", "lon_sec", "lon_dir",
+    ],
+];
+
+// Infobox parameters of the latitude and the longitude in decimal degrees
+const INFOBOX_DECIMAL: &[[&str; 2]] = &[["緯度", "経度"], ["latitude", "longitude"]];
+
+// {{Coord}} and the templates with the coordinates rule, and coordinates in infobox
+// parameters. A template in an infobox parameter is read as a template.
+pub fn parse_coordinates(rules: &TemplateRules, nodes: &[Node]) -> Vec<Coordinate> {
+    let mut coordinates = vec![];
+    visit_nodes(nodes, &mut |node| {
+        if let Node::Template {
+            name, parameters, ..
+        } = node
+        {
+            let name = parse_text_only(name).trim().to_string();
+            match rules.get(name.as_str()) {
+                Some(TemplateRule::Coordinates) => match parse_coord(name.as_str(), parameters) {
+                    Some(coordinate) => coordinates.push(coordinate),
+                    None => debug!("[WARN] Invalid coordinates... [{}]", name),
+                },
+                Some(TemplateRule::Infobox) => {
+                    coordinates.extend(parse_infobox_coordinates(name.as_str(), parameters))
+                }
+                _ => {}
+            }
+        }
+    });
+    coordinates
+}
+
+// {{Coord|35.68|139.76}}, {{Coord|35|41|N|139|41|E}} or
+// {{Coord|35|41|22|N|139|41|30|E|type:city|display=title}}
+fn parse_coord(name: &str, parameters: &[Parameter]) -> Option<Coordinate> {
+    let mut values = vec![];
+    let mut display = String::new();
+    for parameter in parameters {
+        let value = parse_text_only(&parameter.value).trim().to_string();
+        match &parameter.name {
+            Some(key) if parse_text_only(key).trim() == "display" => display = value,
+            Some(_) => {}
+            None if !value.is_empty() => values.push(value),
+            None => {}
+        }
+    }
+    // type:city, region:JP, globe:moon, ... after the coordinates
+    let globe = values.iter().find_map(|v| v.strip_prefix("globe:"));
+    if globe
+        .map(|g| !g.eq_ignore_ascii_case("earth"))
+        .unwrap_or(false)
+    {
+        return None;
+    }
+    let values: Vec<&str> = values
+        .iter()
+        .filter(|v| !v.contains(':'))
+        .map(|v| v.as_str())
+        .collect();
+    let (latitude, longitude) = parse_position(&values)?;
+    Some(Coordinate {
+        latitude,
+        longitude,
+        title: display
+            .split(',')
+            .any(|d| matches!(d.trim(), "title" | "t" | "it" | "ti")),
+        source: name.to_string(),
+    })
+}
+
+// decimal degrees or degrees, minutes and seconds with hemispheres
+fn parse_position(values: &[&str]) -> Option<(f64, f64)> {
+    let is_latitude_hemisphere = |v: &&str| matches!(*v, "N" | "S" | "n" | "s");
+    let is_longitude_hemisphere = |v: &&str| matches!(*v, "E" | "W" | "e" | "w");
+    let (latitude, longitude) = match values.iter().position(is_latitude_hemisphere) {
+        Some(index) => {
+            let rest = &values[index + 1..];
+            let end = rest.iter().position(is_longitude_hemisphere)?;
+            (
+                dms_to_decimal(&values[..index], values[index])?,
+                dms_to_decimal(&rest[..end], rest[end])?,
+            )
+        }
+        None if values.len() >= 2 => (values[0].parse().ok()?, values[1].parse().ok()?),
+        None => return None,
+    };
+    valid_position(latitude, longitude)
+}
+
+fn valid_position(latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+    if latitude.abs() <= 90.0 && longitude.abs() <= 180.0 {
+        Some((round(latitude), round(longitude)))
+    } else {
+        None
+    }
+}
+
+// 6 digits are about 0.1m
+fn round(degrees: f64) -> f64 {
+    (degrees * 1_000_000.0).round() / 1_000_000.0
+}
+
+// [degrees, minutes, seconds] with minutes and seconds optional, S and W are negative
+fn dms_to_decimal(parts: &[&str], hemisphere: &str) -> Option<f64> {
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    let numbers = parts
+        .iter()
+        .map(|p| p.parse::<f64>().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    let degrees = numbers[0];
+    let minutes = numbers.get(1).copied().unwrap_or(0.0);
+    let seconds = numbers.get(2).copied().unwrap_or(0.0);
+    let valid = degrees >= 0.0 && (0.0..60.0).contains(&minutes) && (0.0..60.0).contains(&seconds);
+    if !valid {
+        return None;
+    }
+    let decimal = degrees + minutes / 60.0 + seconds / 3600.0;
+    Some(match hemisphere {
+        "S" | "s" | "W" | "w" => -decimal,
+        _ => decimal,
+    })
+}
+
+// |緯度度=35|緯度分=41|...| or |latitude=35.68|longitude=139.76|
+fn parse_infobox_coordinates(name: &str, parameters: &[Parameter]) -> Option<Coordinate> {
+    let values: HashMap<String, String> = parameters
+        .iter()
+        .filter_map(|p| {
+            let key = parse_text_only(p.name.as_ref()?).trim().to_string();
+            let value = parse_text_only(&p.value).trim().to_string();
+            if value.is_empty() {
+                None
+            } else {
+                Some((key, value))
+            }
+        })
+        .collect();
+    let get = |key: &str| values.get(key).map(|v| v.as_str());
+    let position = INFOBOX_DMS
+        .iter()
+        .find_map(|keys| {
+            let latitude: Vec<&str> = keys[..3].iter().map_while(|k| get(k)).collect();
+            let longitude: Vec<&str> = keys[4..7].iter().map_while(|k| get(k)).collect();
+            if latitude.is_empty() || longitude.is_empty() {
+                return None;
+            }
+            valid_position(
+                dms_to_decimal(&latitude, get(keys[3]).unwrap_or("N"))?,
+                dms_to_decimal(&longitude, get(keys[7]).unwrap_or("E"))?,
+            )
+        })
+        .or_else(|| {
+            INFOBOX_DECIMAL.iter().find_map(|[latitude, longitude]| {
+                valid_position(get(latitude)?.parse().ok()?, get(longitude)?.parse().ok()?)
+            })
+        })?;
+    Some(Coordinate {
+        latitude: position.0,
+        longitude: position.1,
+        title: false,
+        source: name.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+
+    fn coordinates(wikitext: &str) -> Vec<(f64, f64, bool, String)> {
+        let rules = TemplateRules::default();
+        let result = create_ja_config().parse(wikitext);
+        parse_coordinates(&rules, &result.nodes)
+            .into_iter()
+            .map(|c| (c.latitude, c.longitude, c.title, c.source))
+            .collect()
+    }
+
+    #[test]
+    fn coord() {
+        assert_eq!(
+            coordinates("{{Coord|35.681236|139.767125|display=inline,title}}"),
+            vec![(35.681236, 139.767125, true, String::from("Coord"))]
+        );
+        assert_eq!(
+            coordinates("{{coord|35|41|N|139|41|E|type:city}}"),
+            vec![(35.683333, 139.683333, false, String::from("coord"))]
+        );
+        // S and W are negative, aliases are read as Coord
+        assert_eq!(
+            coordinates("{{ウィキ座標|33|51|35.9|S|151|12|40|W}}"),
+            vec![(-33.859972, -151.211111, false, String::from("ウィキ座標"))]
+        );
+    }
+
+    #[test]
+    fn invalid_coord() {
+        assert!(coordinates("{{Coord|95|139}}").is_empty());
+        assert!(coordinates("{{Coord|35|61|N|139|41|E}}").is_empty());
+        assert!(coordinates("{{Coord|10|20|globe:moon}}").is_empty());
+        assert!(coordinates("{{Coord|35|N}}").is_empty());
+    }
+
+    #[test]
+    fn infobox() {
+        assert_eq!(
+            coordinates(
+                "{{基礎情報 日本の市\n|緯度度=35|緯度分=41|緯度秒=22\n\
+                 |経度度=139|経度分=41|経度秒=30\n}}"
+            ),
+            vec![(
+                35.689444,
+                139.691667,
+                false,
+                String::from("基礎情報 日本の市")
+            )]
+        );
+        assert_eq!(
+            coordinates("{{Infobox settlement|latitude=-33.86|longitude=151.21}}"),
+            vec![(-33.86, 151.21, false, String::from("Infobox settlement"))]
+        );
+        assert!(coordinates("{{基礎情報 人物|緯度度=35}}").is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::parser::common_parser::{parse_text_only, visit_nodes};
+use crate::parser::model::{DateRole, DateValue};
+use crate::parser::parser_function::{days_in_month, DateTime};
+use crate::parser::template_parser::{get_param, TemplateRule, TemplateRules};
+use parse_wiki_text::{Node, Parameter};
+
+// A date of a template, the month and the day may be omitted
+#[derive(Clone, Copy)]
+struct PartialDate {
+    year: i64,
+    month: Option<u32>,
+    day: Option<u32>,
+}
+
+impl PartialDate {
+    // the positional parameters index, index + 1 and index + 2 are the year, month and day
+    fn from_parameters(parameters: &[Parameter], index: usize) -> Option<PartialDate> {
+        let number = |index: usize| get_param(parameters, index).and_then(|v| v.parse().ok());
+        let year: i64 = number(index)?;
+        let month = number(index + 1)
+            .map(|m| m as u32)
+            .filter(|m| (1..=12).contains(m));
+        let day = month.and_then(|month| {
+            number(index + 2)
+                .map(|d| d as u32)
+                .filter(|d| *d >= 1 && *d <= days_in_month(year, month))
+        });
+        Some(PartialDate { year, month, day })
+    }
+
+    // 1950年3月4日
+    fn japanese(&self) -> String {
+        match (self.month, self.day) {
+            (Some(month), Some(day)) => format!("{}年{}月{}日", self.year, month, day),
+            (Some(month), None) => format!("{}年{}月", self.year, month),
+            _ => format!("{}年", self.year),
+        }
+    }
+
+    // 1950-03-04, 1950-03 or 1950
+    fn iso(&self) -> String {
+        match (self.month, self.day) {
+            (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", self.year, month, day),
+            (Some(month), None) => format!("{:04}-{:02}", self.year, month),
+            _ => format!("{:04}", self.year),
+        }
+    }
+
+    // full years from self to date, None if a month or a day is omitted
+    fn age_at(&self, date: &PartialDate) -> Option<i64> {
+        let birthday = (self.month?, self.day?);
+        let day = (date.month?, date.day?);
+        let age = date.year - self.year - if day < birthday { 1 } else { 0 };
+        if age >= 0 {
+            Some(age)
+        } else {
+            None
+        }
+    }
+}
+
+// {{年月日|2000|1|2}} is 2000年1月2日, {{生年月日と年齢|1950|3|4}} is 1950年3月4日（70歳）.
+// The age is counted to today as MediaWiki counts it to the time of rendering.
+pub fn render_date(
+    parameters: &[Parameter],
+    age: bool,
+    today: Option<&DateTime>,
+) -> Option<String> {
+    let date = PartialDate::from_parameters(parameters, 1)?;
+    let age = match today {
+        Some(today) if age => date.age_at(&PartialDate {
+            year: today.year,
+            month: Some(today.month),
+            day: Some(today.day),
+        }),
+        _ => None,
+    };
+    Some(match age {
+        Some(age) => format!("{}（{}歳）", date.japanese(), age),
+        None => date.japanese(),
+    })
+}
+
+// {{没年齢|1950|3|4|2000|1|2}} is 49歳没,
+// {{死亡年月日と没年齢|1950|3|4|2000|1|2}} is 2000年1月2日（49歳没）
+pub fn render_death_age(parameters: &[Parameter], death_first: bool, date: bool) -> Option<String> {
+    let (birth, death) = death_dates(parameters, death_first)?;
+    let age = birth.age_at(&death);
+    Some(match (date, age) {
+        (true, Some(age)) => format!("{}（{}歳没）", death.japanese(), age),
+        (true, None) => death.japanese(),
+        (false, Some(age)) => format!("{}歳没", age),
+        (false, None) => String::new(),
+    })
+}
+
+// (birth, death) of the 6 positional parameters
+fn death_dates(parameters: &[Parameter], death_first: bool) -> Option<(PartialDate, PartialDate)> {
+    let first = PartialDate::from_parameters(parameters, 1)?;
+    let second = PartialDate::from_parameters(parameters, 4)?;
+    if death_first {
+        Some((second, first))
+    } else {
+        Some((first, second))
+    }
+}
+
+// ISO 8601 values of the date templates in the page
+pub fn parse_dates(rules: &TemplateRules, nodes: &[Node]) -> Vec<DateValue> {
+    let mut dates = vec![];
+    visit_nodes(nodes, &mut |node| {
+        if let Node::Template {
+            name, parameters, ..
+        } = node
+        {
+            let name = parse_text_only(name).trim().to_string();
+            let mut push = |role: DateRole, date: PartialDate| {
+                dates.push(DateValue {
+                    role,
+                    value: date.iso(),
+                    source: name.to_string(),
+                })
+            };
+            match rules.get(name.as_str()) {
+                Some(TemplateRule::Date { role, .. }) => {
+                    if let Some(date) = PartialDate::from_parameters(parameters, 1) {
+                        push(*role, date);
+                    }
+                }
+                Some(TemplateRule::DeathAge { death_first, .. }) => {
+                    if let Some((birth, death)) = death_dates(parameters, *death_first) {
+                        push(DateRole::Birth, birth);
+                        push(DateRole::Death, death);
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+    dates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+    use crate::parser::template_parser::{parse_template, TemplateContext};
+
+    fn render(wikitext: &str, today: Option<&str>) -> String {
+        let rules = TemplateRules::default();
+        let context = TemplateContext {
+            rules: &rules,
+            today: today.and_then(DateTime::parse),
+        };
+        let result = create_ja_config().parse(wikitext);
+        match result.nodes.first() {
+            Some(Node::Template {
+                name, parameters, ..
+            }) => parse_template(&context, name, parameters).unwrap_or_default(),
+            _ => panic!("no template in [{}]", wikitext),
+        }
+    }
+
+    fn dates(wikitext: &str) -> Vec<(DateRole, String)> {
+        let rules = TemplateRules::default();
+        let result = create_ja_config().parse(wikitext);
+        parse_dates(&rules, &result.nodes)
+            .into_iter()
+            .map(|d| (d.role, d.value))
+            .collect()
+    }
+
+    #[test]
+    fn dates_in_text() {
+        assert_eq!(render("{{年月日|2000|1|2}}", None), "2000年1月2日");
+        assert_eq!(render("{{年月日|2000|13}}", None), "2000年");
+        assert_eq!(render("{{Start date|2000|2|30}}", None), "2000年2月");
+    }
+
+    #[test]
+    fn ages() {
+        let today = Some("2020-03-04T00:00:00Z");
+        assert_eq!(
+            render("{{生年月日と年齢|1950|3|4}}", today),
+            "1950年3月4日（70歳）"
+        );
+        assert_eq!(
+            render("{{生年月日と年齢|1950|3|5}}", today),
+            "1950年3月5日（69歳）"
+        );
+        // without the reference date or the day the age is not written
+        assert_eq!(render("{{生年月日と年齢|1950|3|4}}", None), "1950年3月4日");
+        assert_eq!(render("{{Birth date and age|1950|3}}", today), "1950年3月");
+    }
+
+    #[test]
+    fn death_ages() {
+        assert_eq!(render("{{没年齢|1950|3|4|2000|3|3}}", None), "49歳没");
+        assert_eq!(
+            render("{{死亡年月日と没年齢|1950|3|4|2000|3|4}}", None),
+            "2000年3月4日（50歳没）"
+        );
+        assert_eq!(
+            render("{{Death date and age|2000|3|4|1950|3|4}}", None),
+            "2000年3月4日（50歳没）"
+        );
+    }
+
+    #[test]
+    fn iso_dates() {
+        assert_eq!(
+            dates("{{生年月日と年齢|1950|3|4}} {{End date|2001}} {{年月日|2000|1}}"),
+            vec![
+                (DateRole::Birth, String::from("1950-03-04")),
+                (DateRole::End, String::from("2001")),
+                (DateRole::Date, String::from("2000-01")),
+            ]
+        );
+        assert_eq!(
+            dates("{{Death date and age|2000|3|4|1950|3|4}}"),
+            vec![
+                (DateRole::Birth, String::from("1950-03-04")),
+                (DateRole::Death, String::from("2000-03-04")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::parser::model::{Annotation, AnnotationType};
+use crate::parser::parser_function::days_in_month;
+
+// Era name and its first day in the Gregorian calendar, from 慶長.
+// Before 明治6年 the calendar was lunisolar, so 元年 is the Gregorian year the era
+// started in and the months and the days of those dates are not converted.
+const ERAS: &[(&str, (i64, u32, u32))] = &[
+    ("慶長", (1596, 1, 1)),
+    ("元和", (1615, 1, 1)),
+    ("寛永", (1624, 1, 1)),
+    ("正保", (1644, 1, 1)),
+    ("慶安", (1648, 1, 1)),
+    ("承応", (1652, 1, 1)),
+    ("明暦", (1655, 1, 1)),
+    ("万治", (1658, 1, 1)),
+    ("寛文", (1661, 1, 1)),
+    ("延宝", (1673, 1, 1)),
+    ("天和", (1681, 1, 1)),
+    ("貞享", (1684, 1, 1)),
+    ("元禄", (1688, 1, 1)),
+    ("宝永", (1704, 1, 1)),
+    ("正徳", (1711, 1, 1)),
+    ("享保", (1716, 1, 1)),
+    ("元文", (1736, 1, 1)),
+    ("寛保", (1741, 1, 1)),
+    ("延享", (1744, 1, 1)),
+    ("寛延", (1748, 1, 1)),
+    ("宝暦", (1751, 1, 1)),
+    ("明和", (1764, 1, 1)),
+    ("安永", (1772, 1, 1)),
+    ("天明", (1781, 1, 1)),
+    ("寛政", (1789, 1, 1)),
+    ("享和", (1801, 1, 1)),
+    ("文化", (1804, 1, 1)),
+    ("文政", (1818, 1, 1)),
+    ("天保", (1830, 1, 1)),
+    ("弘化", (1844, 1, 1)),
+    ("嘉永", (1848, 1, 1)),
+    ("安政", (1854, 1, 1)),
+    ("万延", (1860, 1, 1)),
+    ("文久", (1861, 1, 1)),
+    ("元治", (1864, 1, 1)),
+    ("慶応", (1865, 1, 1)),
+    ("明治", (1868, 1, 25)),
+    ("大正", (1912, 7, 30)),
+    ("昭和", (1926, 12, 25)),
+    ("平成", (1989, 1, 8)),
+    ("令和", (2019, 5, 1)),
+];
+
+// the Gregorian calendar was adopted on 明治6年1月1日
+const GREGORIAN_YEAR: i64 = 1873;
+
+// 昭和40年-45年 or 昭和40年〜 is a range, not a date
+const RANGE_MARKS: &[char] = &['-', '－', '–', '—', '〜', '～'];
+
+// 昭和45年, 平成元年, 令和2年3月4日 or 天保年間 in the text as era annotations.
+// The target is the Gregorian date as 1970, 2020-03 or 2020-03-04,
+// or the years of the era as 1830/1844 for 年間, 2019/.. for the current era.
+// Dates out of the era such as 平成元年1月7日 or 大正20年, decades such as 昭和40年代
+// and ranges such as 昭和40年-45年 are skipped.
+pub fn parse_eras(section_index: usize, text: &str) -> Vec<Annotation> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut annotations = vec![];
+    let mut position = 0;
+    while position < chars.len() {
+        match parse_era_date(&chars, position) {
+            Some((end, value)) => {
+                annotations.push(Annotation {
+                    annotation_type: AnnotationType::Era,
+                    section_index,
+                    start: position,
+                    end,
+                    target: Some(value),
+                });
+                position = end;
+            }
+            None => position += 1,
+        }
+    }
+    annotations
+}
+
+// (end, value) of the era date at position
+fn parse_era_date(chars: &[char], position: usize) -> Option<(usize, String)> {
+    let (index, (name, start)) = ERAS.iter().enumerate().find(|(_, (name, _))| {
+        let name: Vec<char> = name.chars().collect();
+        chars[position..].starts_with(&name)
+    })?;
+    let next = ERAS.get(index + 1).map(|(_, start)| *start);
+    let mut cursor = position + name.chars().count();
+    if chars[cursor..].starts_with(&['年', '間']) {
+        let end_year = next
+            .map(|(year, _, _)| year.to_string())
+            .unwrap_or_else(|| String::from(".."));
+        return Some((cursor + 2, format!("{}/{}", start.0, end_year)));
+    }
+    let (year, length) = parse_number(&chars[cursor..], true)?;
+    cursor += length;
+    if chars.get(cursor) != Some(&'年') {
+        return None;
+    }
+    cursor += 1;
+    if chars.get(cursor) == Some(&'代') {
+        return None;
+    }
+    let year = start.0 + year - 1;
+    if year < start.0 || next.map(|next| year > next.0).unwrap_or(false) {
+        return None;
+    }
+    let mut date = (year, None, None);
+    if let Some((month, length)) = parse_number(&chars[cursor..], false) {
+        if chars.get(cursor + length) == Some(&'月') && (1..=12).contains(&month) {
+            cursor += length + 1;
+            date.1 = Some(month as u32);
+            if let Some((day, length)) = parse_number(&chars[cursor..], false) {
+                if chars.get(cursor + length) == Some(&'日')
+                    && day >= 1
+                    && day <= days_in_month(year, month as u32) as i64
+                {
+                    cursor += length + 1;
+                    date.2 = Some(day as u32);
+                }
+            }
+        }
+    }
+    let in_range = |c: Option<&char>| c.map(|c| RANGE_MARKS.contains(c)).unwrap_or(false);
+    if in_range(chars.get(cursor)) || (position > 0 && in_range(chars.get(position - 1))) {
+        return None;
+    }
+    if year < GREGORIAN_YEAR {
+        return Some((cursor, format!("{:04}", year)));
+    }
+    // the month or the day must be in the era
+    let in_era = match date {
+        (year, Some(month), Some(day)) => {
+            (year, month, day) >= *start && next.map(|n| (year, month, day) < n).unwrap_or(true)
+        }
+        (year, Some(month), None) => {
+            (year, month) >= (start.0, start.1)
+                && next.map(|n| (year, month, 1) < n).unwrap_or(true)
+        }
+        _ => true,
+    };
+    if !in_era {
+        return None;
+    }
+    Some((
+        cursor,
+        match date {
+            (year, Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+            (year, Some(month), None) => format!("{:04}-{:02}", year, month),
+            (year, _, _) => format!("{:04}", year),
+        },
+    ))
+}
+
+// (number, length) of ASCII digits, full-width digits or kanji numerals such as 四十五.
+// 元 is 1 for years.
+fn parse_number(chars: &[char], year: bool) -> Option<(i64, usize)> {
+    if year && chars.first() == Some(&'元') {
+        return Some((1, 1));
+    }
+    let digits: Vec<u32> = chars
+        .iter()
+        .map_while(|c| match c {
+            '0'..='9' => c.to_digit(10),
+            '０'..='９' => Some(*c as u32 - '０' as u32),
+            _ => None,
+        })
+        .collect();
+    if !digits.is_empty() {
+        let number = digits.iter().fold(0i64, |n, d| n * 10 + *d as i64);
+        return Some((number, digits.len()));
+    }
+    let kanji: Vec<char> = chars
+        .iter()
+        .copied()
+        .take_while(|c| "〇一二三四五六七八九十".contains(*c))
+        .collect();
+    if kanji.is_empty() {
+        return None;
+    }
+    let digit = |c: char| "〇一二三四五六七八九".chars().position(|k| k == c);
+    let number = match kanji.iter().position(|c| *c == '十') {
+        // 十, 二十, 十五 or 四十五
+        Some(index)
+            if index <= 1
+                && kanji.len() <= index + 2
+                && kanji[index + 1..].iter().all(|c| *c != '十') =>
+        {
+            let tens = match index {
+                0 => 1,
+                _ => digit(kanji[0])?,
+            };
+            let ones = match kanji.get(index + 1) {
+                Some(c) => digit(*c)?,
+                None => 0,
+            };
+            tens * 10 + ones
+        }
+        Some(_) => return None,
+        // 二〇 as digits
+        None => kanji
+            .iter()
+            .try_fold(0, |n, c| digit(*c).map(|d| n * 10 + d))?,
+    };
+    Some((number as i64, kanji.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(text: &str) -> Vec<(usize, usize, String)> {
+        parse_eras(0, text)
+            .into_iter()
+            .map(|a| (a.start, a.end, a.target.unwrap_or_default()))
+            .collect()
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(targets("昭和45年に"), vec![(0, 5, String::from("1970"))]);
+        assert_eq!(targets("平成元年"), vec![(0, 4, String::from("1989"))]);
+        assert_eq!(
+            targets("令和2年3月4日"),
+            vec![(0, 8, String::from("2020-03-04"))]
+        );
+        assert_eq!(targets("昭和四十五年"), vec![(0, 6, String::from("1970"))]);
+        assert_eq!(targets("天保年間"), vec![(0, 4, String::from("1830/1844"))]);
+        assert_eq!(targets("令和年間"), vec![(0, 4, String::from("2019/.."))]);
+        // the month and the day of lunisolar dates are not converted
+        assert_eq!(
+            targets("慶応3年10月14日"),
+            vec![(0, 10, String::from("1867"))]
+        );
+    }
+
+    #[test]
+    fn out_of_era() {
+        assert!(targets("平成元年1月7日").is_empty());
+        assert!(targets("大正20年").is_empty());
+    }
+
+    #[test]
+    fn decades_and_ranges() {
+        assert!(targets("昭和40年代").is_empty());
+        assert!(targets("昭和四十年代").is_empty());
+        assert!(targets("昭和40年-45年").is_empty());
+        assert!(targets("昭和40年〜").is_empty());
+        assert!(targets("昭和40年3月～").is_empty());
+        assert!(targets("1960年-昭和45年").is_empty());
+        assert_eq!(
+            targets("昭和40年代、昭和45年"),
+            vec![(7, 12, String::from("1970"))]
+        );
+    }
+}
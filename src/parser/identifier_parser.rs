@@ -0,0 +1,411 @@
+use crate::parser::common_parser::{parse_text_only, visit_nodes};
+use crate::parser::model::{Identifier, IdentifierType};
+use crate::parser::template_parser::normalize_template_name;
+use log::debug;
+use parse_wiki_text::{Node, Parameter};
+
+// Bibliographic identifiers in template parameters, ISBN and PMID magic links
+// and external link URLs of the page. Identifiers are normalized and the same
+// identifier is returned once. Identifiers that are not valid are skipped.
+pub fn parse_identifiers(nodes: &[Node]) -> Vec<Identifier> {
+    let mut identifiers = vec![];
+    visit_nodes(nodes, &mut |node| match node {
+        Node::Text { value, .. } => parse_magic_links(value, &mut identifiers),
+        Node::ExternalLink { nodes, .. } => {
+            // [url text]
+            if let Some(Node::Text { value, .. }) = nodes.first() {
+                if let Some(url) = value.split_whitespace().next() {
+                    parse_url(url, &mut identifiers);
+                }
+            }
+        }
+        Node::Template {
+            name, parameters, ..
+        } => parse_template(name, parameters, &mut identifiers),
+        _ => {}
+    });
+    identifiers
+}
+
+fn push_identifier(
+    identifier_type: IdentifierType,
+    value: &str,
+    identifiers: &mut Vec<Identifier>,
+) {
+    let value = match normalize_identifier(identifier_type, value) {
+        Some(value) => value,
+        None => {
+            debug!(
+                "[WARN] Invalid identifier... [{:?}:{}]",
+                identifier_type, value
+            );
+            return;
+        }
+    };
+    let identifier = Identifier {
+        identifier_type,
+        value,
+    };
+    if !identifiers.contains(&identifier) {
+        identifiers.push(identifier);
+    }
+}
+
+// |isbn=..., |doi=... and {{ISBN2|...}}, {{doi|...}}
+fn parse_template(name: &[Node], parameters: &[Parameter], identifiers: &mut Vec<Identifier>) {
+    let name = normalize_template_name(parse_text_only(name).as_str()).to_lowercase();
+    if let Some(identifier_type) = template_identifier_type(name.as_str()) {
+        if let Some(parameter) = parameters.iter().find(|p| p.name.is_none()) {
+            push_identifier(
+                identifier_type,
+                parse_text_only(&parameter.value).as_str(),
+                identifiers,
+            );
+        }
+        return;
+    }
+    for parameter in parameters {
+        let key = match &parameter.name {
+            Some(key) => parse_text_only(key).trim().to_lowercase(),
+            None => continue,
+        };
+        let value = parse_text_only(&parameter.value);
+        if let Some(identifier_type) = parameter_identifier_type(key.as_str()) {
+            if !value.trim().is_empty() {
+                push_identifier(identifier_type, value.as_str(), identifiers);
+            }
+        }
+    }
+}
+
+fn template_identifier_type(name: &str) -> Option<IdentifierType> {
+    match name {
+        "isbn" | "isbn2" => Some(IdentifierType::Isbn),
+        "doi" => Some(IdentifierType::Doi),
+        "issn" => Some(IdentifierType::Issn),
+        "pmid" => Some(IdentifierType::Pmid),
+        "ncid" => Some(IdentifierType::Ncid),
+        "ndljp" => Some(IdentifierType::Ndl),
+        _ => None,
+    }
+}
+
+// NDL of {{Normdaten}} is an authority id, ndljp is the digital collection id
+fn parameter_identifier_type(key: &str) -> Option<IdentifierType> {
+    match key {
+        "isbn" | "isbn13" => Some(IdentifierType::Isbn),
+        "doi" => Some(IdentifierType::Doi),
+        "issn" | "eissn" => Some(IdentifierType::Issn),
+        "pmid" => Some(IdentifierType::Pmid),
+        "ncid" => Some(IdentifierType::Ncid),
+        "ndljp" => Some(IdentifierType::Ndl),
+        _ => None,
+    }
+}
+
+// ISBN 978-4-10-109205-8 and PMID 12345 in the text as MediaWiki links them
+fn parse_magic_links(text: &str, identifiers: &mut Vec<Identifier>) {
+    for (prefix, identifier_type) in [
+        ("ISBN", IdentifierType::Isbn),
+        ("PMID", IdentifierType::Pmid),
+    ] {
+        let mut rest = text;
+        while let Some(found) = rest.find(prefix) {
+            let before = rest[..found].chars().last();
+            rest = &rest[found + prefix.len()..];
+            if before.map(|c| c.is_ascii_alphanumeric()).unwrap_or(false) {
+                continue;
+            }
+            let number = rest.trim_start_matches([' ', '\u{a0}']);
+            if number.len() == rest.len() {
+                continue;
+            }
+            let digits = match identifier_type {
+                IdentifierType::Isbn => isbn_digits(number),
+                _ => number.chars().take_while(|c| c.is_ascii_digit()).collect(),
+            };
+            if !digits.is_empty() {
+                push_identifier(identifier_type, digits.as_str(), identifiers);
+            }
+        }
+    }
+}
+
+// digits of ISBN 4-10-109205-2 or ISBN 978 4 10 109205 8 without the text after it
+fn isbn_digits(text: &str) -> String {
+    let mut digits = String::new();
+    for c in text.chars() {
+        let length = if digits.starts_with("978") || digits.starts_with("979") {
+            13
+        } else {
+            10
+        };
+        if c.is_ascii_digit() || (matches!(c, 'X' | 'x') && digits.len() == 9) {
+            digits.push(c);
+        } else if !matches!(c, '-' | ' ') || digits.is_empty() {
+            break;
+        }
+        if digits.len() == length || digits.ends_with(['X', 'x']) {
+            break;
+        }
+    }
+    digits
+}
+
+// https://doi.org/10.1000/xyz, https://ci.nii.ac.jp/ncid/BA12345678, ...
+fn parse_url(url: &str, identifiers: &mut Vec<Identifier>) {
+    const URLS: &[(&str, IdentifierType)] = &[
+        ("doi.org/", IdentifierType::Doi),
+        ("ci.nii.ac.jp/ncid/", IdentifierType::Ncid),
+        ("pubmed.ncbi.nlm.nih.gov/", IdentifierType::Pmid),
+        ("ncbi.nlm.nih.gov/pubmed/", IdentifierType::Pmid),
+        ("dl.ndl.go.jp/pid/", IdentifierType::Ndl),
+        ("dl.ndl.go.jp/info:ndljp/pid/", IdentifierType::Ndl),
+        ("portal.issn.org/resource/issn/", IdentifierType::Issn),
+    ];
+    let lower = url.to_lowercase();
+    for (pattern, identifier_type) in URLS {
+        let start = match lower.find(pattern) {
+            Some(start) => start + pattern.len(),
+            None => continue,
+        };
+        let value = url[start..].split(['?', '#']).next().unwrap_or("");
+        let value = match identifier_type {
+            // a DOI has / in it
+            IdentifierType::Doi => value.replace("%2F", "/").replace("%2f", "/"),
+            _ => value.split('/').next().unwrap_or("").to_string(),
+        };
+        push_identifier(*identifier_type, value.as_str(), identifiers);
+        return;
+    }
+}
+
+// ISBN-10 is converted to ISBN-13, DOI is lowercased. None if the check digit is wrong.
+pub fn normalize_identifier(identifier_type: IdentifierType, value: &str) -> Option<String> {
+    let value = value.trim();
+    match identifier_type {
+        IdentifierType::Isbn => normalize_isbn(value),
+        IdentifierType::Issn => normalize_issn(value),
+        IdentifierType::Doi => {
+            let lower = value.to_lowercase();
+            let doi = lower
+                .strip_prefix("doi:")
+                .unwrap_or(lower.as_str())
+                .trim_start();
+            let doi = match doi.find("doi.org/") {
+                Some(start) => &doi[start + "doi.org/".len()..],
+                None => doi,
+            };
+            let valid =
+                doi.starts_with("10.") && doi.contains('/') && !doi.contains(char::is_whitespace);
+            if valid {
+                Some(doi.to_string())
+            } else {
+                None
+            }
+        }
+        IdentifierType::Pmid => {
+            let pmid = value.trim_start_matches('0');
+            if !pmid.is_empty() && pmid.chars().all(|c| c.is_ascii_digit()) {
+                Some(pmid.to_string())
+            } else {
+                None
+            }
+        }
+        // 2 letters, 7 digits and a check character
+        IdentifierType::Ncid => {
+            let ncid = value.to_uppercase();
+            let chars: Vec<char> = ncid.chars().collect();
+            let valid = chars.len() == 10
+                && chars[..2].iter().all(|c| c.is_ascii_uppercase())
+                && chars[2..9].iter().all(|c| c.is_ascii_digit())
+                && (chars[9].is_ascii_digit() || chars[9] == 'X');
+            if valid {
+                Some(ncid)
+            } else {
+                None
+            }
+        }
+        IdentifierType::Ndl => {
+            if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+                Some(value.to_string())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// digit values with X as 10, None for other characters
+fn check_digits(value: &str, separators: &[char]) -> Option<Vec<u32>> {
+    value
+        .chars()
+        .filter(|c| !separators.contains(c))
+        .map(|c| match c {
+            'X' | 'x' => Some(10),
+            _ => c.to_digit(10),
+        })
+        .collect()
+}
+
+fn normalize_isbn(value: &str) -> Option<String> {
+    let value = value
+        .strip_prefix("ISBN")
+        .or_else(|| value.strip_prefix("isbn"))
+        .unwrap_or(value)
+        .trim_start_matches([':', ' ']);
+    let digits = check_digits(value, &['-', ' '])?;
+    // X is only the last check digit of ISBN-10
+    if digits[..digits.len().saturating_sub(1)].contains(&10) {
+        return None;
+    }
+    match digits.len() {
+        10 => {
+            let sum: u32 = digits
+                .iter()
+                .enumerate()
+                .map(|(i, d)| (10 - i as u32) * d)
+                .sum();
+            if !sum.is_multiple_of(11) {
+                return None;
+            }
+            let mut isbn: Vec<u32> = vec![9, 7, 8];
+            isbn.extend(&digits[..9]);
+            isbn.push(isbn13_check_digit(&isbn));
+            Some(isbn.iter().map(|d| d.to_string()).collect())
+        }
+        13 if (digits[..3] == [9, 7, 8] || digits[..3] == [9, 7, 9])
+            && isbn13_check_digit(&digits[..12]) == digits[12] =>
+        {
+            Some(digits.iter().map(|d| d.to_string()).collect())
+        }
+        _ => None,
+    }
+}
+
+fn isbn13_check_digit(digits: &[u32]) -> u32 {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+// 1234-567X
+fn normalize_issn(value: &str) -> Option<String> {
+    let digits = check_digits(value, &['-', ' '])?;
+    if digits.len() != 8 || digits[..7].contains(&10) {
+        return None;
+    }
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (8 - i as u32) * d)
+        .sum();
+    if !sum.is_multiple_of(11) {
+        return None;
+    }
+    let text: String = digits
+        .iter()
+        .map(|d| {
+            if *d == 10 {
+                'X'
+            } else {
+                char::from_digit(*d, 10).unwrap()
+            }
+        })
+        .collect();
+    Some(format!("{}-{}", &text[..4], &text[4..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+
+    fn isbn(value: &str) -> Option<String> {
+        normalize_identifier(IdentifierType::Isbn, value)
+    }
+
+    fn identifiers(wikitext: &str) -> Vec<(IdentifierType, String)> {
+        let result = create_ja_config().parse(wikitext);
+        parse_identifiers(&result.nodes)
+            .into_iter()
+            .map(|i| (i.identifier_type, i.value))
+            .collect()
+    }
+
+    #[test]
+    fn isbn_10_to_13() {
+        assert_eq!(isbn("4-10-109205-2").as_deref(), Some("9784101092058"));
+        assert_eq!(isbn("ISBN 4101092052").as_deref(), Some("9784101092058"));
+        // X is the check digit 10
+        assert_eq!(isbn("0-8044-2957-X").as_deref(), Some("9780804429573"));
+        assert_eq!(isbn("0-8044-2957-x").as_deref(), Some("9780804429573"));
+    }
+
+    #[test]
+    fn isbn_check_digits() {
+        assert_eq!(isbn("978-4-10-109205-8").as_deref(), Some("9784101092058"));
+        assert_eq!(isbn("978-4-10-109205-7"), None);
+        assert_eq!(isbn("4-10-109205-3"), None);
+        // X only as the last digit of ISBN-10
+        assert_eq!(isbn("0-8044-295X-7"), None);
+        assert_eq!(isbn("977-4-10-109205-8"), None);
+        assert_eq!(isbn("410109205"), None);
+        assert_eq!(isbn(""), None);
+    }
+
+    #[test]
+    fn other_identifiers() {
+        let normalize = normalize_identifier;
+        assert_eq!(
+            normalize(IdentifierType::Issn, "0378-5955").as_deref(),
+            Some("0378-5955")
+        );
+        assert_eq!(
+            normalize(IdentifierType::Issn, "2434561X").as_deref(),
+            Some("2434-561X")
+        );
+        assert_eq!(normalize(IdentifierType::Issn, "0378-5954"), None);
+        assert_eq!(
+            normalize(IdentifierType::Doi, "doi: 10.1000/ABC").as_deref(),
+            Some("10.1000/abc")
+        );
+        assert_eq!(
+            normalize(IdentifierType::Doi, "https://doi.org/10.1000/xyz").as_deref(),
+            Some("10.1000/xyz")
+        );
+        assert_eq!(normalize(IdentifierType::Doi, "11.1000/xyz"), None);
+        assert_eq!(
+            normalize(IdentifierType::Pmid, "00123").as_deref(),
+            Some("123")
+        );
+        assert_eq!(
+            normalize(IdentifierType::Ncid, "ba12345678").as_deref(),
+            Some("BA12345678")
+        );
+        assert_eq!(normalize(IdentifierType::Ncid, "BA1234567"), None);
+    }
+
+    #[test]
+    fn identifiers_in_page() {
+        assert_eq!(
+            identifiers(
+                "ISBN 4-10-109205-2 と ISBN 978-4-10-109205-8、PMID 12345。\
+                 {{Cite book|isbn=4-10-109205-2|doi=10.1000/XYZ}}\
+                 [https://ci.nii.ac.jp/ncid/BA12345678 CiNii] {{ISSN|0378-5955}}"
+            ),
+            vec![
+                (IdentifierType::Isbn, String::from("9784101092058")),
+                (IdentifierType::Pmid, String::from("12345")),
+                (IdentifierType::Doi, String::from("10.1000/xyz")),
+                (IdentifierType::Ncid, String::from("BA12345678")),
+                (IdentifierType::Issn, String::from("0378-5955")),
+            ]
+        );
+        // not a magic link in a word, invalid numbers are skipped
+        assert!(identifiers("XISBN 4-10-109205-2, ISBN 4-10-109205-3").is_empty());
+    }
+}
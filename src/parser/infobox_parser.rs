@@ -0,0 +1,110 @@
+use crate::parser::common_parser::{extract_image, parse_inline_text, parse_text_only};
+use crate::parser::model::{Infobox, InfoboxValue};
+use crate::parser::template_parser::TemplateContext;
+use parse_wiki_text::{Node, Parameter};
+use std::collections::BTreeMap;
+
+// 基礎情報 ○○ or Infobox ○○
+pub fn is_infobox(name: &str) -> bool {
+    let name = name.trim();
+    name.starts_with("基礎情報")
+        || name
+            .get(..7)
+            .map(|prefix| prefix.eq_ignore_ascii_case("infobox"))
+            .unwrap_or(false)
+}
+
+// Parameter name to value. Positional parameters are named "1", "2", ...
+// Parameters without a value are skipped.
+pub fn parse_infobox(
+    context: &TemplateContext,
+    name: &[Node],
+    parameters: &[Parameter],
+) -> Infobox {
+    let mut values = BTreeMap::new();
+    let mut position = 0;
+    for parameter in parameters {
+        let key = match &parameter.name {
+            Some(name) => parse_text_only(name).trim().to_string(),
+            None => {
+                position += 1;
+                position.to_string()
+            }
+        };
+        let value = parse_value(context, &parameter.value);
+        if key.is_empty() || (value.text.is_empty() && value.images.is_empty()) {
+            continue;
+        }
+        values.insert(key, value);
+    }
+    Infobox {
+        infobox_type: parse_text_only(name).trim().to_string(),
+        parameters: values,
+    }
+}
+
+fn parse_value(context: &TemplateContext, nodes: &[Node]) -> InfoboxValue {
+    let mut text = String::new();
+    let mut links = vec![];
+    parse_inline_text(context, nodes, &mut text, &mut links);
+    let images = nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Image { target, text, .. } => Some(extract_image(target, text).target),
+            _ => None,
+        })
+        .collect();
+    InfoboxValue {
+        text: text.split_whitespace().collect::<Vec<&str>>().join(" "),
+        links,
+        images,
+        annotations: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+    use crate::parser::template_parser::TemplateRules;
+
+    fn infobox(wikitext: &str) -> Infobox {
+        let rules = TemplateRules::default();
+        let context = TemplateContext {
+            rules: &rules,
+            today: None,
+        };
+        let result = create_ja_config().parse(wikitext);
+        match result.nodes.first() {
+            Some(Node::Template {
+                name, parameters, ..
+            }) => parse_infobox(&context, name, parameters),
+            _ => panic!("no template in [{}]", wikitext),
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert!(is_infobox("基礎情報 国"));
+        assert!(is_infobox(" Infobox person"));
+        assert!(is_infobox("infobox"));
+        assert!(!is_infobox("Info"));
+        assert!(!is_infobox("Cite web"));
+    }
+
+    #[test]
+    fn parameters() {
+        let infobox = infobox(
+            "{{基礎情報 記号\n|名称 = アンパサンド\n|画像 = [[ファイル:Amp.svg|100px]]\n\
+             |関連 = [[論理積]]、{{lang|en|and}}\n|空 = \n|位置}}",
+        );
+        assert_eq!(infobox.infobox_type, "基礎情報 記号");
+        let keys: Vec<&str> = infobox.parameters.keys().map(|k| k.as_str()).collect();
+        assert_eq!(keys, vec!["1", "名称", "画像", "関連"]);
+        assert_eq!(infobox.parameters["名称"].text, "アンパサンド");
+        assert_eq!(infobox.parameters["画像"].images, vec!["Amp.svg"]);
+        assert_eq!(infobox.parameters["関連"].text, "論理積、and");
+        assert_eq!(infobox.parameters["関連"].links.len(), 1);
+        assert_eq!(infobox.parameters["1"].text, "位置");
+    }
+}
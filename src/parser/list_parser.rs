@@ -1,14 +1,25 @@
+use crate::parser::annotation_parser::{
+    push_end_tag, push_footnotes, push_link_text, push_tag, push_template_text, BOLD, FOOTNOTE,
+    ITALIC,
+};
 use crate::parser::common_parser::{extract_external_link_text, extract_link_text};
 use crate::parser::model::Document;
-use crate::parser::template_parser::parse_template;
+use crate::parser::reference_parser::nested_refs;
+use crate::parser::template_parser::TemplateContext;
 use log::trace;
 use parse_wiki_text::{DefinitionListItem, DefinitionListItemType, ListItem, Node};
 
-pub fn parse_items(items: Vec<ListItem>, doc: &mut Document, indent: u8) -> Vec<String> {
+pub fn parse_items(
+    context: &TemplateContext,
+    items: Vec<ListItem>,
+    doc: &mut Document,
+    indent: u8,
+) -> Vec<String> {
     let mut parsed_items = vec![];
     for item in items {
         let mut parsed_item = String::from("* ");
         for node in item.nodes {
+            let footnotes = nested_refs(&node).len();
             match node {
                 Node::Text { value, .. } => {
                     parsed_item.push_str(value);
@@ -16,58 +27,63 @@ pub fn parse_items(items: Vec<ListItem>, doc: &mut Document, indent: u8) -> Vec<
                 Node::CharacterEntity { character, .. } => parsed_item.push(character),
                 Node::Link { target, text, .. } => {
                     let link_text = extract_link_text(target, &text);
-                    parsed_item.push_str(link_text.clone_text().as_str());
-                    doc.links.push(link_text);
+                    push_link_text(&mut parsed_item, link_text, doc);
                 }
                 Node::ExternalLink { nodes, .. } => {
-                    let link_text = extract_external_link_text(&nodes);
-                    parsed_item.push_str(link_text.clone_text().as_str());
-                    doc.links.push(link_text);
+                    let link_text = extract_external_link_text(context, &nodes);
+                    push_link_text(&mut parsed_item, link_text, doc);
                 }
                 Node::UnorderedList { items, .. } => {
-                    let inner_items = parse_items(items, doc, indent + 1);
+                    let inner_items = parse_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::OrderedList { items, .. } => {
-                    let inner_items = parse_order_items(items, doc, indent + 1);
+                    let inner_items = parse_order_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::DefinitionList { items, .. } => {
-                    let inner_items = parse_definition_items(items, doc, indent + 1);
+                    let inner_items = parse_definition_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::Template {
                     name, parameters, ..
-                } => {
-                    if let Some(template) = parse_template(&name, &parameters) {
-                        parsed_item.push_str(template.as_str());
-                    }
-                }
+                } => push_template_text(context, &mut parsed_item, &name, &parameters),
                 // TODO maybe NO-OP
-                Node::StartTag { .. } => {}
-                Node::EndTag { .. } => {}
+                Node::StartTag { name, .. } => push_tag(&mut parsed_item, &name),
+                Node::EndTag { name, .. } => push_end_tag(&mut parsed_item, &name),
+                Node::Bold { .. } => parsed_item.push(BOLD),
+                Node::BoldItalic { .. } => {
+                    parsed_item.push(BOLD);
+                    parsed_item.push(ITALIC);
+                }
+                Node::Italic { .. } => parsed_item.push(ITALIC),
+                Node::Tag { name, .. } if name == "ref" => parsed_item.push(FOOTNOTE),
                 //NO-OP
-                Node::Bold { .. } => {}
-                Node::BoldItalic { .. } => {}
-                Node::Italic { .. } => {}
                 Node::Comment { .. } => {}
                 _ => {
                     trace!("ぶ    {:?}", node);
                 }
             }
+            push_footnotes(&mut parsed_item, footnotes);
         }
         parsed_items.push(parsed_item);
     }
     return parsed_items;
 }
 
-pub fn parse_order_items(items: Vec<ListItem>, doc: &mut Document, indent: u8) -> Vec<String> {
+pub fn parse_order_items(
+    context: &TemplateContext,
+    items: Vec<ListItem>,
+    doc: &mut Document,
+    indent: u8,
+) -> Vec<String> {
     let mut parsed_items = vec![];
     let mut index = 1;
     for item in items {
         let mut parsed_item = String::from(format!("{}. ", index));
         index += 1;
         for node in item.nodes {
+            let footnotes = nested_refs(&node).len();
             match node {
                 Node::Text { value, .. } => {
                     parsed_item.push_str(value);
@@ -75,40 +91,40 @@ pub fn parse_order_items(items: Vec<ListItem>, doc: &mut Document, indent: u8) -
                 Node::CharacterEntity { character, .. } => parsed_item.push(character),
                 Node::Link { target, text, .. } => {
                     let link_text = extract_link_text(target, &text);
-                    parsed_item.push_str(link_text.clone_text().as_str());
-                    doc.links.push(link_text);
+                    push_link_text(&mut parsed_item, link_text, doc);
                 }
                 Node::UnorderedList { items, .. } => {
-                    let inner_items = parse_items(items, doc, indent + 1);
+                    let inner_items = parse_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items)
                 }
                 Node::OrderedList { items, .. } => {
-                    let inner_items = parse_order_items(items, doc, indent + 1);
+                    let inner_items = parse_order_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::DefinitionList { items, .. } => {
-                    let inner_items = parse_definition_items(items, doc, indent + 1);
+                    let inner_items = parse_definition_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::Template {
                     name, parameters, ..
-                } => {
-                    if let Some(template) = parse_template(&name, &parameters) {
-                        parsed_item.push_str(template.as_str());
-                    }
-                }
+                } => push_template_text(context, &mut parsed_item, &name, &parameters),
                 // TODO maybe NO-OP
-                Node::StartTag { .. } => {}
-                Node::EndTag { .. } => {}
+                Node::StartTag { name, .. } => push_tag(&mut parsed_item, &name),
+                Node::EndTag { name, .. } => push_end_tag(&mut parsed_item, &name),
+                Node::Bold { .. } => parsed_item.push(BOLD),
+                Node::BoldItalic { .. } => {
+                    parsed_item.push(BOLD);
+                    parsed_item.push(ITALIC);
+                }
+                Node::Italic { .. } => parsed_item.push(ITALIC),
+                Node::Tag { name, .. } if name == "ref" => parsed_item.push(FOOTNOTE),
                 //NO-OP
-                Node::Bold { .. } => {}
-                Node::BoldItalic { .. } => {}
-                Node::Italic { .. } => {}
                 Node::Comment { .. } => {}
                 _ => {
                     trace!("り    {:?}", node);
                 }
             }
+            push_footnotes(&mut parsed_item, footnotes);
         }
         parsed_items.push(parsed_item);
     }
@@ -126,6 +142,7 @@ fn indent_items(indent: u8, parsed_item: &mut String, inner_items: Vec<String>)
 }
 
 pub fn parse_definition_items(
+    context: &TemplateContext,
     items: Vec<DefinitionListItem>,
     doc: &mut Document,
     indent: u8,
@@ -137,6 +154,7 @@ pub fn parse_definition_items(
             parsed_item.push_str("  ");
         }
         for node in item.nodes {
+            let footnotes = nested_refs(&node).len();
             match node {
                 Node::Text { value, .. } => {
                     parsed_item.push_str(value);
@@ -144,45 +162,44 @@ pub fn parse_definition_items(
                 Node::CharacterEntity { character, .. } => parsed_item.push(character),
                 Node::Link { target, text, .. } => {
                     let link_text = extract_link_text(target, &text);
-                    parsed_item.push_str(link_text.clone_text().as_str());
-                    doc.links.push(link_text);
+                    push_link_text(&mut parsed_item, link_text, doc);
                 }
                 Node::ExternalLink { nodes, .. } => {
-                    let link_text = extract_external_link_text(&nodes);
-                    parsed_item.push_str(link_text.clone_text().as_str());
-                    doc.links.push(link_text);
+                    let link_text = extract_external_link_text(context, &nodes);
+                    push_link_text(&mut parsed_item, link_text, doc);
                 }
                 Node::UnorderedList { items, .. } => {
-                    let inner_items = parse_items(items, doc, indent + 1);
+                    let inner_items = parse_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::OrderedList { items, .. } => {
-                    let inner_items = parse_order_items(items, doc, indent + 1);
+                    let inner_items = parse_order_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::DefinitionList { items, .. } => {
-                    let inner_items = parse_definition_items(items, doc, indent + 1);
+                    let inner_items = parse_definition_items(context, items, doc, indent + 1);
                     indent_items(indent, &mut parsed_item, inner_items);
                 }
                 Node::Template {
                     name, parameters, ..
-                } => {
-                    if let Some(template) = parse_template(&name, &parameters) {
-                        parsed_item.push_str(template.as_str());
-                    }
-                }
+                } => push_template_text(context, &mut parsed_item, &name, &parameters),
                 // TODO maybe NO-OP
-                Node::StartTag { .. } => {}
-                Node::EndTag { .. } => {}
+                Node::StartTag { name, .. } => push_tag(&mut parsed_item, &name),
+                Node::EndTag { name, .. } => push_end_tag(&mut parsed_item, &name),
+                Node::Bold { .. } => parsed_item.push(BOLD),
+                Node::BoldItalic { .. } => {
+                    parsed_item.push(BOLD);
+                    parsed_item.push(ITALIC);
+                }
+                Node::Italic { .. } => parsed_item.push(ITALIC),
+                Node::Tag { name, .. } if name == "ref" => parsed_item.push(FOOTNOTE),
                 //NO-OP
-                Node::Bold { .. } => {}
-                Node::BoldItalic { .. } => {}
-                Node::Italic { .. } => {}
                 Node::Comment { .. } => {}
                 _ => {
                     trace!("で    {:?}", node);
                 }
             }
+            push_footnotes(&mut parsed_item, footnotes);
         }
         parsed_items.push(parsed_item);
     }
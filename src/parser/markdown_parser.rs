@@ -0,0 +1,496 @@
+use crate::parser::common_parser::{extract_external_link_text, parse_text_only};
+use crate::parser::model::{Link, Text};
+use crate::parser::template_parser::{parse_template, TemplateContext};
+use log::trace;
+use parse_wiki_text::{DefinitionListItem, DefinitionListItemType, ListItem, Node, TableRow};
+
+// Render a page to Markdown, keeping headings, lists, links, emphasis, tables and images.
+pub fn parse_markdown(context: &TemplateContext, title: &str, nodes: &[Node]) -> String {
+    let mut markdown = MarkdownParser::new(context);
+    markdown.out.push_str("# ");
+    markdown
+        .out
+        .push_str(escape_markdown(title, false).as_str());
+    markdown.out.push_str("\n\n");
+    markdown.parse_blocks(nodes);
+    let mut out = String::new();
+    // blocks and the text after them may both add line breaks
+    let mut newlines = 0;
+    for c in markdown.finish().trim_end().chars() {
+        newlines = if c == '\n' { newlines + 1 } else { 0 };
+        if newlines <= 2 {
+            out.push(c);
+        }
+    }
+    out.push('\n');
+    out
+}
+
+// Render nodes as one line of inline Markdown.
+pub fn parse_markdown_inline(context: &TemplateContext, nodes: &[Node]) -> String {
+    let mut markdown = MarkdownParser::new(context);
+    markdown.parse_inline(nodes);
+    markdown.finish().trim().replace('\n', " ")
+}
+
+struct MarkdownParser<'a> {
+    context: &'a TemplateContext<'a>,
+    out: String,
+    bold: bool,
+    italic: bool,
+    // inside <code>, text is not escaped
+    code: bool,
+}
+
+impl<'a> MarkdownParser<'a> {
+    fn new(context: &'a TemplateContext<'a>) -> Self {
+        MarkdownParser {
+            context,
+            out: String::new(),
+            bold: false,
+            italic: false,
+            code: false,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.close_emphasis();
+        self.out
+    }
+
+    // '' and ''' are toggles in wikitext and may be left open at the end of a paragraph
+    fn close_emphasis(&mut self) {
+        if self.italic {
+            self.out.push('*');
+            self.italic = false;
+        }
+        if self.bold {
+            self.out.push_str("**");
+            self.bold = false;
+        }
+    }
+
+    fn start_block(&mut self) {
+        self.close_emphasis();
+        let trimmed = self.out.trim_end_matches([' ', '\n']).len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() {
+            self.out.push_str("\n\n");
+        }
+    }
+
+    fn parse_blocks(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Heading { level, nodes, .. } => {
+                    self.start_block();
+                    self.out.push_str("#".repeat(*level as usize).as_str());
+                    self.out.push(' ');
+                    self.out
+                        .push_str(parse_markdown_inline(self.context, nodes).as_str());
+                    self.out.push_str("\n\n");
+                }
+                Node::ParagraphBreak { .. } => {
+                    self.start_block();
+                }
+                Node::HorizontalDivider { .. } => {
+                    self.start_block();
+                    self.out.push_str("---\n\n");
+                }
+                Node::UnorderedList { items, .. } => {
+                    self.start_block();
+                    self.parse_list(items, false, 0);
+                    self.out.push('\n');
+                }
+                Node::OrderedList { items, .. } => {
+                    self.start_block();
+                    self.parse_list(items, true, 0);
+                    self.out.push('\n');
+                }
+                Node::DefinitionList { items, .. } => {
+                    self.start_block();
+                    self.parse_definition_list(items, 0);
+                    self.out.push('\n');
+                }
+                Node::Table { captions, rows, .. } => {
+                    self.start_block();
+                    for caption in captions {
+                        self.out.push('*');
+                        self.out.push_str(
+                            parse_markdown_inline(self.context, &caption.content).as_str(),
+                        );
+                        self.out.push_str("*\n\n");
+                    }
+                    self.parse_table(rows);
+                    self.out.push('\n');
+                }
+                Node::Preformatted { nodes, .. } => {
+                    self.start_block();
+                    self.push_code_block(parse_text_only(nodes).as_str());
+                }
+                Node::Image { target, text, .. } => {
+                    self.start_block();
+                    self.push_image(target, text);
+                    self.out.push_str("\n\n");
+                }
+                Node::Tag { name, nodes, .. } => match name.as_ref() {
+                    "pre" | "source" | "syntaxhighlight" => {
+                        self.start_block();
+                        self.push_code_block(parse_text_only(nodes).as_str());
+                    }
+                    _ => self.parse_inline_node(node),
+                },
+                // page metadata, not rendered
+                Node::Category { .. } | Node::Redirect { .. } => {}
+                _ => self.parse_inline_node(node),
+            }
+        }
+    }
+
+    fn parse_inline(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.parse_inline_node(node);
+        }
+    }
+
+    fn parse_inline_node(&mut self, node: &Node) {
+        match node {
+            Node::Text { value, .. } => {
+                if *value != "\n" {
+                    self.push_text(value);
+                }
+            }
+            Node::CharacterEntity { character, .. } => {
+                self.push_text(character.encode_utf8(&mut [0; 4]))
+            }
+            Node::Bold { .. } => {
+                self.out.push_str("**");
+                self.bold = !self.bold;
+            }
+            Node::Italic { .. } => {
+                self.out.push('*');
+                self.italic = !self.italic;
+            }
+            Node::BoldItalic { .. } => {
+                self.out.push_str("***");
+                self.bold = !self.bold;
+                self.italic = !self.italic;
+            }
+            Node::Link { target, text, .. } => {
+                let text = parse_markdown_inline(self.context, text);
+                push_link(&mut self.out, text.as_str(), target);
+            }
+            Node::ExternalLink { nodes, .. } => {
+                if let Text::LinkText {
+                    text,
+                    link: Link::ExternalLink { link_target },
+                } = extract_external_link_text(self.context, nodes)
+                {
+                    let text = escape_markdown(text.as_str(), false);
+                    push_link(&mut self.out, text.as_str(), link_target.as_str());
+                }
+            }
+            Node::Image { target, text, .. } => self.push_image(target, text),
+            Node::Template {
+                name, parameters, ..
+            } => {
+                if let Some(template) = parse_template(self.context, name, parameters) {
+                    self.push_text(template.as_str());
+                }
+            }
+            Node::Tag { name, nodes, .. } => match name.as_ref() {
+                "math" => {
+                    self.out.push('$');
+                    self.out.push_str(parse_text_only(nodes).trim());
+                    self.out.push('$');
+                }
+                "nowiki" | "poem" => self.push_text(parse_text_only(nodes).as_str()),
+                // references and other extension tags are not rendered
+                _ => {}
+            },
+            Node::StartTag { name, .. } if name == "br" => self.out.push_str("  \n"),
+            Node::StartTag { name, .. } if name == "code" => {
+                self.out.push('`');
+                self.code = true;
+            }
+            Node::EndTag { name, .. } if name == "code" => {
+                self.out.push('`');
+                self.code = false;
+            }
+            Node::StartTag { .. } | Node::EndTag { .. } => {}
+            Node::Comment { .. } | Node::MagicWord { .. } => {}
+            // blocks inside inline context (e.g. a list in a table cell) are flattened
+            Node::UnorderedList { items, .. } | Node::OrderedList { items, .. } => {
+                for item in items {
+                    self.out.push(' ');
+                    self.parse_inline(&item.nodes);
+                }
+            }
+            _ => {
+                trace!("ま    {:?}", node);
+            }
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.code {
+            self.out.push_str(text);
+        } else {
+            let line_start = self.out.is_empty() || self.out.ends_with('\n');
+            self.out
+                .push_str(escape_markdown(text, line_start).as_str());
+        }
+    }
+
+    fn parse_list(&mut self, items: &[ListItem], ordered: bool, indent: usize) {
+        for (index, item) in items.iter().enumerate() {
+            let marker = if ordered {
+                format!("{}. ", index + 1)
+            } else {
+                String::from("- ")
+            };
+            self.out.push_str("  ".repeat(indent).as_str());
+            self.out.push_str(marker.as_str());
+            self.parse_list_item(&item.nodes, indent + 1);
+        }
+    }
+
+    fn parse_definition_list(&mut self, items: &[DefinitionListItem], indent: usize) {
+        for item in items {
+            self.out.push_str("  ".repeat(indent).as_str());
+            match item.type_ {
+                DefinitionListItemType::Term => {
+                    let term = parse_markdown_inline(self.context, &item.nodes);
+                    self.out.push_str("**");
+                    self.out.push_str(term.as_str());
+                    self.out.push_str("**\n");
+                }
+                DefinitionListItemType::Details => {
+                    self.out.push_str(": ");
+                    self.parse_list_item(&item.nodes, indent + 1);
+                }
+            }
+        }
+    }
+
+    // the item text on the current line, nested lists on the following lines
+    fn parse_list_item(&mut self, nodes: &[Node], indent: usize) {
+        let mut inline = MarkdownParser::new(self.context);
+        let mut nested = vec![];
+        for node in nodes {
+            match node {
+                Node::UnorderedList { .. }
+                | Node::OrderedList { .. }
+                | Node::DefinitionList { .. } => nested.push(node),
+                _ => inline.parse_inline_node(node),
+            }
+        }
+        self.out
+            .push_str(inline.finish().trim().replace('\n', " ").as_str());
+        self.out.push('\n');
+        for node in nested {
+            match node {
+                Node::UnorderedList { items, .. } => self.parse_list(items, false, indent),
+                Node::OrderedList { items, .. } => self.parse_list(items, true, indent),
+                Node::DefinitionList { items, .. } => self.parse_definition_list(items, indent),
+                _ => {}
+            }
+        }
+    }
+
+    // GitHub flavored Markdown table. The first row is the header row.
+    fn parse_table(&mut self, rows: &[TableRow]) {
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| {
+                        parse_markdown_inline(self.context, &cell.content).replace('|', "\\|")
+                    })
+                    .collect()
+            })
+            .filter(|cells: &Vec<String>| !cells.is_empty())
+            .collect();
+        let columns = rows.iter().map(|cells| cells.len()).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        for (index, cells) in rows.iter().enumerate() {
+            self.out.push('|');
+            for column in 0..columns {
+                self.out.push(' ');
+                self.out
+                    .push_str(cells.get(column).map(|c| c.as_str()).unwrap_or(""));
+                self.out.push_str(" |");
+            }
+            self.out.push('\n');
+            if index == 0 {
+                self.out.push('|');
+                for _ in 0..columns {
+                    self.out.push_str(" --- |");
+                }
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn push_code_block(&mut self, code: &str) {
+        self.out.push_str("```\n");
+        self.out.push_str(code.trim_matches('\n'));
+        self.out.push_str("\n```\n\n");
+    }
+
+    // ![caption](file name)
+    fn push_image(&mut self, target: &str, text: &[Node]) {
+        let file = target
+            .split_once(':')
+            .map(|(_, file)| file)
+            .unwrap_or(target);
+        let caption = parse_markdown_inline(self.context, text);
+        self.out.push_str("![");
+        self.out.push_str(image_caption(caption.as_str()));
+        self.out.push_str("](");
+        self.out.push_str(link_destination(file).as_str());
+        self.out.push(')');
+    }
+}
+
+// image options such as thumb|right|200px come before the caption
+fn image_caption(text: &str) -> &str {
+    let caption = text.rsplit('|').next().unwrap_or("").trim();
+    let option = caption.ends_with("px")
+        || caption.starts_with("alt=")
+        || caption.starts_with("link=")
+        || caption.starts_with("upright")
+        || IMAGE_OPTIONS.contains(&caption);
+    if option {
+        ""
+    } else {
+        caption
+    }
+}
+
+const IMAGE_OPTIONS: &[&str] = &[
+    "thumb",
+    "thumbnail",
+    "frame",
+    "frameless",
+    "border",
+    "left",
+    "right",
+    "center",
+    "none",
+    "サムネイル",
+    "左",
+    "右",
+    "中央",
+];
+
+// Escape emphasis, code, link and HTML characters, and headings, quotes and list markers
+// at the start of a line, so article text is not read as Markdown.
+// `|` is escaped by parse_table.
+fn escape_markdown(text: &str, line_start: bool) -> String {
+    let mut escaped = String::new();
+    let mut line_start = line_start;
+    // digits at the start of a line, "1." is an ordered list
+    let mut number = false;
+    for c in text.chars() {
+        let escape = match c {
+            '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '~' => true,
+            '#' | '>' | '-' | '+' | '=' => line_start,
+            '.' | ')' => number,
+            _ => false,
+        };
+        if escape {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        number = (line_start || number) && c.is_ascii_digit();
+        line_start = c == '\n' || (line_start && (c == ' ' || c == '\t'));
+    }
+    escaped
+}
+
+fn push_link(out: &mut String, text: &str, target: &str) {
+    out.push('[');
+    if text.is_empty() {
+        out.push_str(escape_markdown(target, false).as_str());
+    } else {
+        out.push_str(text);
+    }
+    out.push_str("](");
+    out.push_str(link_destination(target).as_str());
+    out.push(')');
+}
+
+// wrap a link destination in <> when it contains characters Markdown does not allow
+fn link_destination(target: &str) -> String {
+    if target.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        format!("<{}>", target)
+    } else {
+        target.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+    use crate::parser::template_parser::TemplateRules;
+
+    fn markdown(wikitext: &str) -> String {
+        let rules = TemplateRules::default();
+        let context = TemplateContext {
+            rules: &rules,
+            today: None,
+        };
+        let result = create_ja_config().parse(wikitext);
+        parse_markdown(&context, "東京", &result.nodes)
+    }
+
+    #[test]
+    fn headings_and_inline() {
+        assert_eq!(
+            markdown(
+                "'''東京'''は[[日本]]の''首都''。{{lang|en|Tokyo}}\n\n== 歴史 ==\n[[江戸時代|江戸]]、[https://example.com 公式]"
+            ),
+            "# 東京\n\n**東京**は[日本](日本)の*首都*。Tokyo\n\n## 歴史\n\n[江戸](江戸時代)、[公式](https://example.com)\n"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            markdown("* a\n** b\n# c\n# d\n; 用語\n: 説明"),
+            "# 東京\n\n- a\n  - b\n\n1. c\n2. d\n\n**用語**\n: 説明\n"
+        );
+    }
+
+    #[test]
+    fn table_and_image() {
+        assert_eq!(
+            markdown(
+                "{|\n! a !! b\n|-\n| 1 || 2\n|}\n[[ファイル:Tokyo tower.jpg|thumb|東京タワー]]"
+            ),
+            "# 東京\n\n| a | b |\n| --- | --- |\n| 1 | 2 |\n\n![東京タワー](<Tokyo tower.jpg>)\n"
+        );
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            markdown("a*b*_c_ [x] `y` [[A_B]]\n\n<nowiki># 1</nowiki>\n\n- 2. <code>*z*</code>"),
+            "# 東京\n\na\\*b\\*\\_c\\_ \\[x\\] \\`y\\` [A\\_B](A_B)\n\n\\# 1\n\n\\- 2. `*z*`\n"
+        );
+        assert_eq!(
+            markdown("<nowiki>10. 項目</nowiki>\n{|\n| <nowiki>a|b</nowiki>\n|}"),
+            "# 東京\n\n10\\. 項目\n\n| a\\|b |\n| --- |\n"
+        );
+    }
+
+    #[test]
+    fn unclosed_emphasis() {
+        assert_eq!(markdown("'''太字\n\n次"), "# 東京\n\n**太字**\n\n次\n");
+    }
+}
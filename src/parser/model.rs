@@ -1,8 +1,9 @@
 use log::warn;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Document {
     pub id: String,
     pub title: String,
@@ -14,11 +15,275 @@ pub struct Document {
     pub headings: Vec<String>,
     pub images: Vec<Image>,
     pub links: Vec<Text>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub annotations: Vec<Annotation>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub provenance: Vec<Provenance>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub tables: Vec<Table>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub references: Vec<Reference>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub footnotes: Vec<Footnote>,
+    // citation templates in page order
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub citations: Vec<Citation>,
+    // ISBN, DOI, ... of the page
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub identifiers: Vec<Identifier>,
+    // ids of the authority control template
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub authority_ids: Vec<AuthorityId>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub coordinates: Vec<Coordinate>,
+    // dates of the date templates
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub dates: Vec<DateValue>,
+    // readings of the title and of ruby text
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub readings: Vec<Reading>,
+    // infobox templates in page order
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub infobox: Vec<Infobox>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sections: Vec<Section>,
+    // text before the first heading
+    #[serde(rename = "abstract", skip_serializing_if = "Option::is_none")]
+    pub abstract_text: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub redirect_to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markdown: Option<String>,
+    // only for section and paragraph granularity
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub passage: Option<Passage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Infobox {
+    // template name such as "基礎情報 国" or "Infobox person"
+    #[serde(rename = "type")]
+    pub infobox_type: String,
+    pub parameters: BTreeMap<String, InfoboxValue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InfoboxValue {
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub links: Vec<Text>,
+    // file names of images in the value
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub images: Vec<String>,
+    // era annotations of the text with section_index 0
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub annotations: Vec<Annotation>,
+}
+
+// Content of a <ref> tag. Named refs used more than once are one reference.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reference {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub links: Vec<Text>,
+    // content of the tag as written in the wikitext
+    pub wikitext: String,
+}
+
+// Where a <ref> tag was, as a character offset in doc.contents[section_index]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Footnote {
+    pub reference_index: usize,
+    pub section_index: usize,
+    pub offset: usize,
+}
+
+// A citation template such as {{Cite web}}. Fields that are not written are None.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    // template name such as "Cite web"
+    #[serde(rename = "type")]
+    pub citation_type: String,
+    // the reference the template is in, None for lists of 参考文献
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub reference_index: Option<usize>,
+    pub section_index: usize,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub authors: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub publisher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub isbn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub doi: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub access_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub language: Option<String>,
+}
+
+// A normalized bibliographic identifier such as ISBN-13
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identifier {
+    #[serde(rename = "type")]
+    pub identifier_type: IdentifierType,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentifierType {
+    Isbn,
+    Doi,
+    Issn,
+    Pmid,
+    Ncid,
+    // NDL Digital Collections
+    Ndl,
+}
+
+// An id of the subject in an authority database such as VIAF or NDL.
+// The id of WIKIDATA is None when the template uses the Wikidata item of the page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorityId {
+    pub source: String,
+    pub id: Option<String>,
+}
+
+// A position in decimal degrees, south and west are negative
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coordinate {
+    pub latitude: f64,
+    pub longitude: f64,
+    // the coordinate of the page shown at the title
+    pub title: bool,
+    // template name such as "Coord", or the infobox name for infobox parameters
+    pub source: String,
+}
+
+// An ISO 8601 date of a date template: 1950-03-04, 1950-03 or 1950
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DateValue {
+    pub role: DateRole,
+    pub value: String,
+    // template name such as "生年月日と年齢"
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateRole {
+    Birth,
+    Death,
+    Start,
+    End,
+    #[default]
+    Date,
+}
+
+// A reading of the text in hiragana and in katakana
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reading {
+    pub text: String,
+    pub hiragana: String,
+    pub katakana: String,
+    // "DEFAULTSORT", "category", "ruby" or the template name such as "読み仮名"
+    pub source: String,
+}
+
+// A table in doc.contents[section_index]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Table {
+    pub section_index: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub captions: Vec<String>,
+    // number of leading rows that have only header cells
+    pub header_rows: usize,
+    pub rows: Vec<Vec<TableCell>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableCell {
+    pub text: String,
+    pub header: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub links: Vec<Text>,
+}
+
+// Where a span of doc.contents[section_index] comes from in the wikitext.
+// start and end are characters, source_start and source_end are bytes of the wikitext.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub section_index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub source_start: usize,
+    pub source_end: usize,
+}
+
+// A span in doc.contents[section_index], offsets are in characters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(rename = "type")]
+    pub annotation_type: AnnotationType,
+    pub section_index: usize,
+    pub start: usize,
+    pub end: usize,
+    // link target, language code, Gregorian date of era or reading of ruby
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationType {
+    Link,
+    ExternalLink,
+    Bold,
+    Italic,
+    Lang,
+    // 和暦 date, the target is the Gregorian date
+    Era,
+    // base text of ruby, the target is the reading
+    Ruby,
+}
+
+// A section and its subsections. The lead section has no title and level 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Section {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub level: u8,
+    pub text: String,
+    pub links: Vec<Text>,
+    pub images: Vec<Image>,
+    pub sections: Vec<Section>,
+}
+
+// Position of a section or paragraph record in its page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Passage {
+    pub page_id: String,
+    pub section_index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paragraph_index: Option<usize>,
+    // heading level, 0 for the lead section
+    pub level: u8,
+    // headings from the top level section to this section
+    pub heading_path: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ImageType {
     Image,
     File,
@@ -41,14 +306,14 @@ impl FromStr for ImageType {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Image {
     pub target: String,
     pub target_type: ImageType,
     pub text: Text,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Text {
     LinkText {
@@ -70,7 +335,7 @@ impl Text {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Link {
     ExternalLink { link_target: String },
@@ -0,0 +1,552 @@
+// Helpers for the ParserFunctions extension used by template_expander.
+
+// MediaWiki writes errors like this and #iferror looks for class="error"
+pub fn error_text(message: &str) -> String {
+    format!("<strong class=\"error\">{}</strong>", message)
+}
+
+pub fn is_error(text: &str) -> bool {
+    text.contains("class=\"error\"")
+}
+
+// #ifeq and #switch compare numbers as numbers
+pub fn values_equal(left: &str, right: &str) -> bool {
+    match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+// #expr
+pub fn evaluate_expression(expression: &str) -> Result<String, String> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(String::new());
+    }
+    let mut parser = ExpressionParser { tokens, index: 0 };
+    let value = parser.parse_or()?;
+    match parser.tokens.get(parser.index) {
+        Some(token) => Err(format!(
+            "Expression error: Unexpected {}.",
+            token.describe()
+        )),
+        None => Ok(format_number(value)),
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NAN")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "INF" } else { "-INF" })
+    } else if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    // operators and words such as div, mod, round
+    Operator(String),
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(value) => format!("number {}", format_number(*value)),
+            Token::Operator(operator) => format!("{} operator", operator),
+            Token::Open => String::from("( operator"),
+            Token::Close => String::from("closing bracket"),
+        }
+    }
+}
+
+const WORDS: &[&str] = &[
+    "e", "pi", "div", "mod", "round", "and", "or", "not", "ceil", "floor", "trunc", "abs", "sqrt",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = vec![];
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                index += 1;
+            }
+            let number: String = chars[start..index].iter().collect();
+            let value = number
+                .parse()
+                .map_err(|_| format!("Expression error: Invalid number {}.", number))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() {
+            let start = index;
+            while index < chars.len() && chars[index].is_ascii_alphabetic() {
+                index += 1;
+            }
+            let word: String = chars[start..index]
+                .iter()
+                .collect::<String>()
+                .to_lowercase();
+            if !WORDS.contains(&word.as_str()) {
+                return Err(format!("Expression error: Unrecognized word \"{}\".", word));
+            }
+            tokens.push(Token::Operator(word));
+        } else {
+            let two: String = chars[index..chars.len().min(index + 2)].iter().collect();
+            let operator = match two.as_str() {
+                "<=" | ">=" | "<>" | "!=" => two,
+                _ => match c {
+                    '(' => {
+                        tokens.push(Token::Open);
+                        index += 1;
+                        continue;
+                    }
+                    ')' => {
+                        tokens.push(Token::Close);
+                        index += 1;
+                        continue;
+                    }
+                    '+' | '-' | '*' | '/' | '^' | '=' | '<' | '>' => c.to_string(),
+                    _ => {
+                        return Err(format!(
+                            "Expression error: Unrecognized punctuation character \"{}\".",
+                            c
+                        ))
+                    }
+                },
+            };
+            index += operator.chars().count();
+            tokens.push(Token::Operator(operator));
+        }
+    }
+    Ok(tokens)
+}
+
+// Precedence from low to high: or, and, comparison, round, + -, * / div mod, ^, e, unary
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl ExpressionParser {
+    fn next_operator(&self, operators: &[&str]) -> Option<String> {
+        match self.tokens.get(self.index) {
+            Some(Token::Operator(operator)) if operators.contains(&operator.as_str()) => {
+                Some(operator.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_and()?;
+        while self.next_operator(&["or"]).is_some() {
+            self.index += 1;
+            let right = self.parse_and()?;
+            left = bool_value(left != 0.0 || right != 0.0);
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_comparison()?;
+        while self.next_operator(&["and"]).is_some() {
+            self.index += 1;
+            let right = self.parse_comparison()?;
+            left = bool_value(left != 0.0 && right != 0.0);
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_round()?;
+        while let Some(operator) = self.next_operator(&["=", "<>", "!=", "<", ">", "<=", ">="]) {
+            self.index += 1;
+            let right = self.parse_round()?;
+            left = bool_value(match operator.as_str() {
+                "=" => left == right,
+                "<>" | "!=" => left != right,
+                "<" => left < right,
+                ">" => left > right,
+                "<=" => left <= right,
+                _ => left >= right,
+            });
+        }
+        Ok(left)
+    }
+
+    fn parse_round(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_additive()?;
+        while self.next_operator(&["round"]).is_some() {
+            self.index += 1;
+            let digits = self.parse_additive()?.trunc() as i32;
+            let scale = 10f64.powi(digits);
+            left = (left * scale).round() / scale;
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_multiplicative()?;
+        while let Some(operator) = self.next_operator(&["+", "-"]) {
+            self.index += 1;
+            let right = self.parse_multiplicative()?;
+            left = if operator == "+" {
+                left + right
+            } else {
+                left - right
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_power()?;
+        while let Some(operator) = self.next_operator(&["*", "/", "div", "mod"]) {
+            self.index += 1;
+            let right = self.parse_power()?;
+            left = match operator.as_str() {
+                "*" => left * right,
+                "mod" => {
+                    let right = right.trunc();
+                    if right == 0.0 {
+                        return Err(String::from("Division by zero."));
+                    }
+                    left.trunc() % right
+                }
+                _ => {
+                    if right == 0.0 {
+                        return Err(String::from("Division by zero."));
+                    }
+                    left / right
+                }
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_power(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_exponent()?;
+        while self.next_operator(&["^"]).is_some() {
+            self.index += 1;
+            let right = self.parse_exponent()?;
+            left = left.powf(right);
+        }
+        Ok(left)
+    }
+
+    // 2e3 is 2 * 10 ^ 3
+    fn parse_exponent(&mut self) -> Result<f64, String> {
+        let mut left = self.parse_unary()?;
+        while self.next_operator(&["e"]).is_some() {
+            self.index += 1;
+            let right = self.parse_unary()?;
+            left *= 10f64.powf(right);
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<f64, String> {
+        let operator =
+            match self.next_operator(&["+", "-", "not", "ceil", "floor", "trunc", "abs", "sqrt"]) {
+                Some(operator) => operator,
+                None => return self.parse_primary(),
+            };
+        self.index += 1;
+        let value = self.parse_unary()?;
+        Ok(match operator.as_str() {
+            "+" => value,
+            "-" => -value,
+            "not" => bool_value(value == 0.0),
+            "ceil" => value.ceil(),
+            "floor" => value.floor(),
+            "trunc" => value.trunc(),
+            "abs" => value.abs(),
+            _ => value.sqrt(),
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<f64, String> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Operator(operator)) if operator == "pi" => Ok(std::f64::consts::PI),
+            Some(Token::Operator(operator)) if operator == "e" => Ok(std::f64::consts::E),
+            Some(Token::Open) => {
+                let value = self.parse_or()?;
+                match self.tokens.get(self.index) {
+                    Some(Token::Close) => {
+                        self.index += 1;
+                        Ok(value)
+                    }
+                    _ => Err(String::from("Expression error: Unclosed bracket.")),
+                }
+            }
+            Some(token) => Err(format!(
+                "Expression error: Unexpected {}.",
+                token.describe()
+            )),
+            None => Err(String::from("Expression error: Missing operand.")),
+        }
+    }
+}
+
+fn bool_value(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    // 2019-08-14T00:23:07Z, 2019-08-14 00:23:07, 2019-08-14, 2019-08, 2019,
+    // 20190814002307 or 2019年8月14日
+    pub fn parse(text: &str) -> Option<DateTime> {
+        let text = text.trim();
+        let numbers: Vec<&str> = text
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|n| !n.is_empty())
+            .collect();
+        let compact = numbers.len() == 1 && numbers[0].len() == 14;
+        let values: Vec<i64> = if compact {
+            let n = numbers[0];
+            [
+                &n[0..4],
+                &n[4..6],
+                &n[6..8],
+                &n[8..10],
+                &n[10..12],
+                &n[12..14],
+            ]
+            .iter()
+            .map(|v| v.parse().unwrap())
+            .collect()
+        } else {
+            numbers
+                .iter()
+                .map(|n| n.parse().ok())
+                .collect::<Option<Vec<i64>>>()?
+        };
+        if values.is_empty() || values.len() > 6 || (!compact && numbers[0].len() != 4) {
+            return None;
+        }
+        let value = |index: usize, default: i64| values.get(index).copied().unwrap_or(default);
+        let date = DateTime {
+            year: value(0, 1970),
+            month: value(1, 1) as u32,
+            day: value(2, 1) as u32,
+            hour: value(3, 0) as u32,
+            minute: value(4, 0) as u32,
+            second: value(5, 0) as u32,
+        };
+        let valid = (1..=12).contains(&date.month)
+            && date.day >= 1
+            && date.day <= days_in_month(date.year, date.month)
+            && date.hour < 24
+            && date.minute < 60
+            && date.second < 60;
+        if valid {
+            Some(date)
+        } else {
+            None
+        }
+    }
+
+    // days since 1970-01-01
+    fn days(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day)
+    }
+
+    // 0 is Sunday
+    fn weekday(&self) -> i64 {
+        (self.days() + 4).rem_euclid(7)
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+const WEEKDAYS: &[&str] = &["日", "月", "火", "水", "木", "金", "土"];
+
+// #time with PHP date format characters, names are Japanese.
+// "quoted text" and \x are written as is.
+pub fn format_time(format: &str, date: &DateTime) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            'Y' => out.push_str(date.year.to_string().as_str()),
+            'y' => out.push_str(format!("{:02}", date.year.rem_euclid(100)).as_str()),
+            'L' => out.push(if is_leap_year(date.year) { '1' } else { '0' }),
+            'n' => out.push_str(date.month.to_string().as_str()),
+            'm' => out.push_str(format!("{:02}", date.month).as_str()),
+            'F' | 'M' => out.push_str(format!("{}月", date.month).as_str()),
+            'j' => out.push_str(date.day.to_string().as_str()),
+            'd' => out.push_str(format!("{:02}", date.day).as_str()),
+            't' => out.push_str(days_in_month(date.year, date.month).to_string().as_str()),
+            'z' => {
+                let day_of_year = date.days() - days_from_civil(date.year, 1, 1);
+                out.push_str(day_of_year.to_string().as_str())
+            }
+            'D' => out.push_str(WEEKDAYS[date.weekday() as usize]),
+            'l' => {
+                out.push_str(WEEKDAYS[date.weekday() as usize]);
+                out.push_str("曜日")
+            }
+            'w' => out.push_str(date.weekday().to_string().as_str()),
+            'N' => {
+                let weekday = date.weekday();
+                out.push_str(if weekday == 0 { 7 } else { weekday }.to_string().as_str())
+            }
+            'G' => out.push_str(date.hour.to_string().as_str()),
+            'H' => out.push_str(format!("{:02}", date.hour).as_str()),
+            'g' => out.push_str(((date.hour + 11) % 12 + 1).to_string().as_str()),
+            'h' => out.push_str(format!("{:02}", (date.hour + 11) % 12 + 1).as_str()),
+            'A' => out.push_str(if date.hour < 12 { "午前" } else { "午後" }),
+            'a' => out.push_str(if date.hour < 12 { "am" } else { "pm" }),
+            'i' => out.push_str(format!("{:02}", date.minute).as_str()),
+            's' => out.push_str(format!("{:02}", date.second).as_str()),
+            'U' => {
+                let seconds = date.days() * 86400
+                    + date.hour as i64 * 3600
+                    + date.minute as i64 * 60
+                    + date.second as i64;
+                out.push_str(seconds.to_string().as_str())
+            }
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
+            '"' => {
+                for next in chars.by_ref() {
+                    if next == '"' {
+                        break;
+                    }
+                    out.push(next);
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expr(expression: &str) -> String {
+        evaluate_expression(expression).unwrap_or_else(|e| e)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(expr("1 + 2 * 3"), "7");
+        assert_eq!(expr("(1 + 2) * 3"), "9");
+        assert_eq!(expr("2 ^ 3 * 2"), "16");
+        assert_eq!(expr("-2 ^ 2"), "4");
+        assert_eq!(expr("2e3 + 1"), "2001");
+        assert_eq!(expr("10 - 2 - 3"), "5");
+        assert_eq!(expr("1 + 1 = 2"), "1");
+        assert_eq!(expr("1 or 0 and 0"), "1");
+        assert_eq!(expr("not 0 and 1"), "1");
+        assert_eq!(expr("1 + 2.345 round 1"), "3.3");
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(expr("10 div 4"), "2.5");
+        assert_eq!(expr("10 / 4"), "2.5");
+        assert_eq!(expr("7 mod 3"), "1");
+        assert_eq!(expr("-7.5 mod 2"), "-1");
+        assert_eq!(expr("floor -1.5"), "-2");
+        assert_eq!(expr("abs -3"), "3");
+        assert_eq!(expr("2 <> 3"), "1");
+        assert_eq!(expr("2 >= 3"), "0");
+        assert_eq!(expr(""), "");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(expr("1 / 0"), "Division by zero.");
+        assert_eq!(expr("1 +"), "Expression error: Missing operand.");
+        assert_eq!(expr("(1 + 2"), "Expression error: Unclosed bracket.");
+        assert_eq!(expr("1 2"), "Expression error: Unexpected number 2.");
+    }
+
+    #[test]
+    fn values() {
+        assert!(values_equal("1", "1.0"));
+        assert!(values_equal("abc", "abc"));
+        assert!(!values_equal("01a", "1a"));
+        assert!(is_error(&error_text("Error")));
+    }
+
+    #[test]
+    fn date_time() {
+        let date = DateTime::parse("2019-08-14T00:23:07Z").unwrap();
+        assert_eq!((date.year, date.month, date.day), (2019, 8, 14));
+        assert_eq!((date.hour, date.minute, date.second), (0, 23, 7));
+        let date = DateTime::parse("20190814002307").unwrap();
+        assert_eq!((date.year, date.minute), (2019, 23));
+        let date = DateTime::parse("2019年8月14日").unwrap();
+        assert_eq!((date.month, date.day), (8, 14));
+        assert!(DateTime::parse("2019-02-29").is_none());
+        assert!(DateTime::parse("2020-02-29").is_some());
+        assert!(DateTime::parse("19-08-14").is_none());
+    }
+
+    #[test]
+    fn time_format() {
+        let date = DateTime::parse("2019-08-14T13:05:09Z").unwrap();
+        assert_eq!(format_time("Y-m-d H:i:s", &date), "2019-08-14 13:05:09");
+        assert_eq!(format_time("Y年n月j日 (D)", &date), "2019年8月14日 (水)");
+        assert_eq!(format_time("l A g時", &date), "水曜日 午後 1時");
+        assert_eq!(format_time("z t L", &date), "225 31 0");
+        assert_eq!(format_time("U", &date), "1565787909");
+        assert_eq!(format_time("\"Y\" \\Y", &date), "Y Y");
+    }
+}
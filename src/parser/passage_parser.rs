@@ -0,0 +1,622 @@
+use crate::parser::model::{
+    Annotation, Citation, Document, Footnote, Image, Passage, Provenance, Reference, Section,
+    Table, Text,
+};
+use crate::parser::provenance_parser::Segment;
+use crate::parser::sentence_parser::split_sentences;
+use log::warn;
+use std::str::FromStr;
+
+pub enum Granularity {
+    Page,
+    Section,
+    Paragraph,
+}
+
+impl FromStr for Granularity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "page" => Ok(Granularity::Page),
+            "section" => Ok(Granularity::Section),
+            "paragraph" => Ok(Granularity::Paragraph),
+            _ => {
+                warn!("Unexpected Granularity: [{}]", s);
+                Err(())
+            }
+        }
+    }
+}
+
+// Collected while extracting a page, one per `Document.contents` entry.
+pub struct SectionInfo {
+    // heading level, 0 for the lead section
+    pub level: u8,
+    // doc.links.len() and doc.images.len() when the section started
+    pub links_start: usize,
+    pub images_start: usize,
+    // byte offsets of paragraph breaks in the section content
+    pub paragraph_breaks: Vec<usize>,
+    // text written for each node of the section
+    pub segments: Vec<Segment>,
+}
+
+impl SectionInfo {
+    pub fn new(level: u8, doc: &Document) -> Self {
+        SectionInfo {
+            level,
+            links_start: doc.links.len(),
+            images_start: doc.images.len(),
+            paragraph_breaks: vec![],
+            segments: vec![],
+        }
+    }
+}
+
+pub fn split_document(
+    doc: Document,
+    sections: &[SectionInfo],
+    granularity: &Granularity,
+) -> Vec<Document> {
+    match granularity {
+        Granularity::Page => vec![doc],
+        Granularity::Section => split_sections(&doc, sections),
+        Granularity::Paragraph => split_paragraphs(&doc, sections),
+    }
+}
+
+// One document per section with the id `<page_id>#<section_index>`.
+// The heading is moved from the text to `headings` and `heading_path`.
+pub fn split_sections(doc: &Document, sections: &[SectionInfo]) -> Vec<Document> {
+    let mut documents = vec![];
+    for (index, section) in iterate_sections(doc, sections) {
+        let (heading, text) = section_text(doc, index);
+        let mut document = section_document(doc, sections, index, section);
+        document.id = format!("{}#{}", doc.id, index);
+        document.headings = heading.into_iter().map(|h| h.to_string()).collect();
+        document.contents = vec![text.trim().to_string()];
+        document.annotations = annotations_in(doc, index, text.trim());
+        document.provenance = provenance_in(doc, index, text.trim());
+        document.tables = tables_in(doc, index);
+        (document.references, document.footnotes, document.citations) =
+            references_in(doc, index, text.trim());
+        document.citations.extend(bibliography_in(doc, index));
+        documents.push(document);
+    }
+    documents
+}
+
+// One document per paragraph with the id `<page_id>#<section_index>.<paragraph_index>`.
+// Empty paragraphs are skipped.
+pub fn split_paragraphs(doc: &Document, sections: &[SectionInfo]) -> Vec<Document> {
+    let mut documents = vec![];
+    for (index, section) in iterate_sections(doc, sections) {
+        let (heading, _) = section_text(doc, index);
+        for (paragraph_index, paragraph) in section_paragraphs(doc, index, section)
+            .into_iter()
+            .enumerate()
+        {
+            let mut document = section_document(doc, sections, index, section);
+            document.id = format!("{}#{}.{}", doc.id, index, paragraph_index);
+            document.headings = heading.into_iter().map(|h| h.to_string()).collect();
+            document.contents = vec![paragraph.to_string()];
+            document.annotations = annotations_in(doc, index, paragraph);
+            document.provenance = provenance_in(doc, index, paragraph);
+            (document.references, document.footnotes, document.citations) =
+                references_in(doc, index, paragraph);
+            if let Some(passage) = document.passage.as_mut() {
+                passage.paragraph_index = Some(paragraph_index);
+            }
+            documents.push(document);
+        }
+    }
+    documents
+}
+
+// non-empty paragraphs of the section text
+fn section_paragraphs<'a>(doc: &'a Document, index: usize, section: &SectionInfo) -> Vec<&'a str> {
+    let content = doc.contents[index].as_str();
+    let (heading, _) = section_text(doc, index);
+    let text_start = heading.map(|h| h.len() + 1).unwrap_or(0).min(content.len());
+    let mut starts = vec![text_start];
+    starts.extend(
+        section
+            .paragraph_breaks
+            .iter()
+            .filter(|offset| **offset > text_start && **offset <= content.len()),
+    );
+    starts.push(content.len());
+    starts
+        .windows(2)
+        .map(|range| content[range[0]..range[1]].trim())
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect()
+}
+
+pub struct AbstractSettings {
+    // cut at the end of the first paragraph
+    pub first_paragraph: bool,
+    // cut after N sentences, 0 for no limit
+    pub sentences: usize,
+}
+
+// The text before the first heading
+pub fn parse_abstract(
+    doc: &Document,
+    sections: &[SectionInfo],
+    settings: &AbstractSettings,
+) -> Option<String> {
+    let section = sections.first()?;
+    if doc.contents.is_empty() {
+        return None;
+    }
+    let paragraphs = section_paragraphs(doc, 0, section);
+    let text = if settings.first_paragraph {
+        paragraphs.first()?.to_string()
+    } else {
+        paragraphs.join("\n")
+    };
+    let text = if settings.sentences > 0 {
+        split_sentences(text.as_str())
+            .into_iter()
+            .take(settings.sentences)
+            .collect::<Vec<&str>>()
+            .join("")
+    } else {
+        text
+    };
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+// (section index, section info) for sections that have content
+fn iterate_sections<'a>(
+    doc: &'a Document,
+    sections: &'a [SectionInfo],
+) -> impl Iterator<Item = (usize, &'a SectionInfo)> {
+    sections.iter().enumerate().take(doc.contents.len())
+}
+
+// contents[0] is the lead section, contents[i] starts with headings[i - 1] and a line break
+fn section_text(doc: &Document, index: usize) -> (Option<&str>, &str) {
+    let content = doc.contents[index].as_str();
+    if index == 0 {
+        return (None, content);
+    }
+    let heading = doc.headings.get(index - 1).map(|h| h.as_str());
+    let text = match heading {
+        Some(heading) if content.starts_with(heading) => &content[heading.len()..],
+        _ => content,
+    };
+    (heading, text)
+}
+
+// the section's links and images, its place in the page and the page level data
+fn section_document(
+    doc: &Document,
+    sections: &[SectionInfo],
+    index: usize,
+    section: &SectionInfo,
+) -> Document {
+    let (links, images) = section_links(doc, sections, index, section);
+    Document {
+        id: doc.id.to_string(),
+        title: doc.title.to_string(),
+        timestamp: doc.timestamp.to_string(),
+        revision_id: doc.revision_id.to_string(),
+        url: doc.url.to_string(),
+        contents: vec![],
+        categories: doc.categories.clone(),
+        headings: vec![],
+        images: images.to_vec(),
+        links: links.to_vec(),
+        annotations: vec![],
+        provenance: vec![],
+        tables: vec![],
+        references: vec![],
+        footnotes: vec![],
+        citations: vec![],
+        identifiers: doc.identifiers.clone(),
+        authority_ids: doc.authority_ids.clone(),
+        coordinates: doc.coordinates.clone(),
+        dates: doc.dates.clone(),
+        readings: doc.readings.clone(),
+        infobox: doc.infobox.clone(),
+        sections: vec![],
+        abstract_text: None,
+        redirect_to: doc.redirect_to.clone(),
+        markdown: None,
+        passage: Some(Passage {
+            page_id: doc.id.to_string(),
+            section_index: index,
+            paragraph_index: None,
+            level: section.level,
+            heading_path: heading_path(doc, sections, index),
+        }),
+    }
+}
+
+// character range of `text`, a slice of doc.contents[index]
+fn char_range(doc: &Document, index: usize, text: &str) -> (usize, usize) {
+    let content = doc.contents[index].as_str();
+    let byte_start = text.as_ptr() as usize - content.as_ptr() as usize;
+    let start = content[..byte_start].chars().count();
+    (start, start + text.chars().count())
+}
+
+// Annotations inside `text`, a slice of doc.contents[index].
+// Offsets are moved to be relative to `text`.
+fn annotations_in(doc: &Document, index: usize, text: &str) -> Vec<Annotation> {
+    let (start, end) = char_range(doc, index, text);
+    doc.annotations
+        .iter()
+        .filter(|a| a.section_index == index && a.start >= start && a.end <= end)
+        .map(|a| Annotation {
+            section_index: 0,
+            start: a.start - start,
+            end: a.end - start,
+            ..a.clone()
+        })
+        .collect()
+}
+
+// Provenance inside `text`, a slice of doc.contents[index].
+fn provenance_in(doc: &Document, index: usize, text: &str) -> Vec<Provenance> {
+    let (start, end) = char_range(doc, index, text);
+    doc.provenance
+        .iter()
+        .filter(|p| p.section_index == index && p.start >= start && p.end <= end)
+        .map(|p| Provenance {
+            section_index: 0,
+            start: p.start - start,
+            end: p.end - start,
+            ..p.clone()
+        })
+        .collect()
+}
+
+// Footnotes inside `text`, a slice of doc.contents[index], the references they use
+// and the citations in the references. reference_index points to the returned references.
+fn references_in(
+    doc: &Document,
+    index: usize,
+    text: &str,
+) -> (Vec<Reference>, Vec<Footnote>, Vec<Citation>) {
+    let (start, end) = char_range(doc, index, text);
+    let mut references: Vec<Reference> = vec![];
+    let mut reference_indexes: Vec<usize> = vec![];
+    let mut footnotes = vec![];
+    for footnote in doc
+        .footnotes
+        .iter()
+        .filter(|f| f.section_index == index && f.offset >= start && f.offset <= end)
+    {
+        let reference_index = match reference_indexes
+            .iter()
+            .position(|i| *i == footnote.reference_index)
+        {
+            Some(position) => position,
+            None => {
+                let reference = match doc.references.get(footnote.reference_index) {
+                    Some(reference) => reference,
+                    None => continue,
+                };
+                reference_indexes.push(footnote.reference_index);
+                references.push(reference.clone());
+                references.len() - 1
+            }
+        };
+        footnotes.push(Footnote {
+            reference_index,
+            section_index: 0,
+            offset: footnote.offset - start,
+        });
+    }
+    let citations = doc
+        .citations
+        .iter()
+        .filter_map(|c| {
+            let reference_index = reference_indexes
+                .iter()
+                .position(|i| Some(*i) == c.reference_index)?;
+            Some(Citation {
+                reference_index: Some(reference_index),
+                section_index: 0,
+                ..c.clone()
+            })
+        })
+        .collect();
+    (references, footnotes, citations)
+}
+
+// citations outside of references in the section, only for section records
+fn bibliography_in(doc: &Document, index: usize) -> Vec<Citation> {
+    doc.citations
+        .iter()
+        .filter(|c| c.reference_index.is_none() && c.section_index == index)
+        .map(|c| Citation {
+            section_index: 0,
+            ..c.clone()
+        })
+        .collect()
+}
+
+// tables of the section, only for section records
+fn tables_in(doc: &Document, index: usize) -> Vec<Table> {
+    doc.tables
+        .iter()
+        .filter(|t| t.section_index == index)
+        .map(|t| Table {
+            section_index: 0,
+            ..t.clone()
+        })
+        .collect()
+}
+
+// links and images found in the section
+fn section_links<'a>(
+    doc: &'a Document,
+    sections: &[SectionInfo],
+    index: usize,
+    section: &SectionInfo,
+) -> (&'a [Text], &'a [Image]) {
+    let (links_end, images_end) = match sections.get(index + 1) {
+        Some(next) => (next.links_start, next.images_start),
+        None => (doc.links.len(), doc.images.len()),
+    };
+    (
+        &doc.links[section.links_start..links_end],
+        &doc.images[section.images_start..images_end],
+    )
+}
+
+fn heading_path(doc: &Document, sections: &[SectionInfo], index: usize) -> Vec<String> {
+    let mut path: Vec<(u8, &str)> = vec![];
+    for (i, section) in sections.iter().enumerate().take(index + 1).skip(1) {
+        while let Some((level, _)) = path.last() {
+            if *level >= section.level {
+                path.pop();
+            } else {
+                break;
+            }
+        }
+        if let Some(heading) = doc.headings.get(i - 1) {
+            path.push((section.level, heading.as_str()));
+        }
+    }
+    path.into_iter().map(|(_, h)| h.to_string()).collect()
+}
+
+// Build the section tree. The lead section is always a top level section.
+pub fn parse_sections(doc: &Document, sections: &[SectionInfo]) -> Vec<Section> {
+    let mut roots = vec![];
+    // open sections, from the top level to the current one
+    let mut stack: Vec<Section> = vec![];
+    for (index, info) in iterate_sections(doc, sections) {
+        let (heading, text) = section_text(doc, index);
+        let (links, images) = section_links(doc, sections, index, info);
+        let section = Section {
+            title: heading.map(|h| h.to_string()),
+            level: info.level,
+            text: text.trim().to_string(),
+            links: links.to_vec(),
+            images: images.to_vec(),
+            sections: vec![],
+        };
+        if index == 0 {
+            roots.push(section);
+            continue;
+        }
+        close_sections(&mut stack, &mut roots, section.level);
+        stack.push(section);
+    }
+    close_sections(&mut stack, &mut roots, 0);
+    roots
+}
+
+// attach open sections at `level` or deeper to their parents
+fn close_sections(stack: &mut Vec<Section>, roots: &mut Vec<Section>, level: u8) {
+    while stack.last().map(|s| s.level >= level).unwrap_or(false) {
+        let section = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some(parent) => parent.sections.push(section),
+            None => roots.push(section),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(text: &str) -> Text {
+        Text::Text {
+            text: text.to_string(),
+        }
+    }
+
+    // lead, 歴史 (level 2), 近代 (level 3) and 関連項目 (level 2)
+    fn page() -> (Document, Vec<SectionInfo>) {
+        let mut doc = Document {
+            id: String::from("1"),
+            ..Default::default()
+        };
+        let mut sections = vec![];
+        let mut push = |doc: &mut Document, level: u8, content: &str, breaks: Vec<usize>| {
+            let mut section = SectionInfo::new(level, doc);
+            section.paragraph_breaks = breaks;
+            sections.push(section);
+            doc.contents.push(content.to_string());
+            doc.links.push(link(content));
+        };
+        let lead = "リード文。\n次の段落。";
+        push(&mut doc, 0, lead, vec![lead.find('\n').unwrap()]);
+        push(&mut doc, 2, "歴史\n本文。", vec![]);
+        push(&mut doc, 3, "近代\n近代の本文。", vec![]);
+        push(&mut doc, 2, "関連項目\n項目。", vec![]);
+        doc.headings = vec![
+            String::from("歴史"),
+            String::from("近代"),
+            String::from("関連項目"),
+        ];
+        (doc, sections)
+    }
+
+    // a short page without headings
+    fn stub() -> (Document, Vec<SectionInfo>) {
+        let mut doc = Document {
+            id: String::from("2"),
+            ..Default::default()
+        };
+        let lead = "スタブ記事。二文目。\n次の段落。";
+        let mut section = SectionInfo::new(0, &doc);
+        section.paragraph_breaks = vec![lead.find('\n').unwrap()];
+        doc.contents.push(lead.to_string());
+        doc.links.push(link("記事"));
+        (doc, vec![section])
+    }
+
+    #[test]
+    fn sections() {
+        let (doc, sections) = page();
+        let documents = split_sections(&doc, &sections);
+        let ids: Vec<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["1#0", "1#1", "1#2", "1#3"]);
+        assert_eq!(documents[0].contents, vec!["リード文。\n次の段落。"]);
+        assert!(documents[0].headings.is_empty());
+        assert_eq!(documents[2].contents, vec!["近代の本文。"]);
+        assert_eq!(documents[2].headings, vec!["近代"]);
+        assert_eq!(documents[2].links.len(), 1);
+        assert_eq!(documents[2].links[0].clone_text(), "近代\n近代の本文。");
+        let passage = documents[2].passage.as_ref().unwrap();
+        assert_eq!(passage.section_index, 2);
+        assert_eq!(passage.level, 3);
+        assert_eq!(passage.heading_path, vec!["歴史", "近代"]);
+        let passage = documents[3].passage.as_ref().unwrap();
+        assert_eq!(passage.heading_path, vec!["関連項目"]);
+    }
+
+    #[test]
+    fn paragraphs() {
+        let (doc, sections) = page();
+        let documents = split_paragraphs(&doc, &sections);
+        let ids: Vec<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["1#0.0", "1#0.1", "1#1.0", "1#2.0", "1#3.0"]);
+        assert_eq!(documents[0].contents, vec!["リード文。"]);
+        assert_eq!(documents[1].contents, vec!["次の段落。"]);
+        assert_eq!(
+            documents[1].passage.as_ref().unwrap().paragraph_index,
+            Some(1)
+        );
+        assert_eq!(documents[2].contents, vec!["本文。"]);
+    }
+
+    #[test]
+    fn section_tree() {
+        let (doc, sections) = page();
+        let roots = parse_sections(&doc, &sections);
+        let titles: Vec<Option<&str>> = roots.iter().map(|s| s.title.as_deref()).collect();
+        assert_eq!(titles, vec![None, Some("歴史"), Some("関連項目")]);
+        assert_eq!(roots[1].sections.len(), 1);
+        assert_eq!(roots[1].sections[0].title.as_deref(), Some("近代"));
+        assert_eq!(roots[1].sections[0].text, "近代の本文。");
+    }
+
+    #[test]
+    fn section_tree_without_headings() {
+        let (doc, sections) = stub();
+        let roots = parse_sections(&doc, &sections);
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].title, None);
+        assert_eq!(roots[0].level, 0);
+        assert_eq!(roots[0].text, "スタブ記事。二文目。\n次の段落。");
+        assert_eq!(roots[0].links.len(), 1);
+        assert!(roots[0].sections.is_empty());
+    }
+
+    #[test]
+    fn section_tree_skipped_levels() {
+        let (mut doc, mut sections) = page();
+        // 近代 is a level 4 heading under 歴史, then a level 3 heading
+        sections[2].level = 4;
+        // 現代 has no links
+        let mut section = SectionInfo::new(3, &doc);
+        section.links_start = sections[3].links_start;
+        sections.insert(3, section);
+        doc.contents.insert(3, String::from("現代\n現代の本文。"));
+        doc.headings.insert(2, String::from("現代"));
+        let roots = parse_sections(&doc, &sections);
+        assert_eq!(roots.len(), 3);
+        let children: Vec<Option<&str>> = roots[1]
+            .sections
+            .iter()
+            .map(|s| s.title.as_deref())
+            .collect();
+        assert_eq!(children, vec![Some("近代"), Some("現代")]);
+        assert!(roots[2].sections.is_empty());
+    }
+
+    #[test]
+    fn abstract_text() {
+        let (doc, sections) = page();
+        let settings = AbstractSettings {
+            first_paragraph: true,
+            sentences: 0,
+        };
+        assert_eq!(
+            parse_abstract(&doc, &sections, &settings).as_deref(),
+            Some("リード文。")
+        );
+    }
+
+    #[test]
+    fn abstract_settings() {
+        let (doc, sections) = page();
+        let whole = AbstractSettings {
+            first_paragraph: false,
+            sentences: 0,
+        };
+        assert_eq!(
+            parse_abstract(&doc, &sections, &whole).as_deref(),
+            Some("リード文。\n次の段落。")
+        );
+        let sentences = AbstractSettings {
+            first_paragraph: false,
+            sentences: 1,
+        };
+        assert_eq!(
+            parse_abstract(&doc, &sections, &sentences).as_deref(),
+            Some("リード文。")
+        );
+        let empty = Document {
+            contents: vec![String::new()],
+            ..doc
+        };
+        assert_eq!(parse_abstract(&empty, &sections, &whole), None);
+    }
+
+    #[test]
+    fn abstract_without_headings() {
+        let (doc, sections) = stub();
+        let first_paragraph = AbstractSettings {
+            first_paragraph: true,
+            sentences: 0,
+        };
+        assert_eq!(
+            parse_abstract(&doc, &sections, &first_paragraph).as_deref(),
+            Some("スタブ記事。二文目。")
+        );
+        let sentences = AbstractSettings {
+            first_paragraph: false,
+            sentences: 3,
+        };
+        assert_eq!(
+            parse_abstract(&doc, &sections, &sentences).as_deref(),
+            Some("スタブ記事。二文目。次の段落。")
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::parser::model::{Document, Provenance};
+use crate::parser::passage_parser::SectionInfo;
+use parse_wiki_text::Node;
+
+// Text written for one node, collected while extracting a page.
+pub struct Segment {
+    // byte range in the section content
+    pub start: usize,
+    pub end: usize,
+    // byte range in the wikitext of the page
+    pub source_start: usize,
+    pub source_end: usize,
+}
+
+// byte range of the node in the wikitext
+pub fn node_range(node: &Node) -> (usize, usize) {
+    match node {
+        Node::Bold { start, end }
+        | Node::BoldItalic { start, end }
+        | Node::Category { start, end, .. }
+        | Node::CharacterEntity { start, end, .. }
+        | Node::Comment { start, end }
+        | Node::DefinitionList { start, end, .. }
+        | Node::EndTag { start, end, .. }
+        | Node::ExternalLink { start, end, .. }
+        | Node::Heading { start, end, .. }
+        | Node::HorizontalDivider { start, end }
+        | Node::Image { start, end, .. }
+        | Node::Italic { start, end }
+        | Node::Link { start, end, .. }
+        | Node::MagicWord { start, end }
+        | Node::OrderedList { start, end, .. }
+        | Node::ParagraphBreak { start, end }
+        | Node::Parameter { start, end, .. }
+        | Node::Preformatted { start, end, .. }
+        | Node::Redirect { start, end, .. }
+        | Node::StartTag { start, end, .. }
+        | Node::Table { start, end, .. }
+        | Node::Tag { start, end, .. }
+        | Node::Template { start, end, .. }
+        | Node::Text { start, end, .. }
+        | Node::UnorderedList { start, end, .. } => (*start, *end),
+    }
+}
+
+// Character ranges of the segments in doc.contents, whitespace around the text is left out.
+pub fn parse_provenance(doc: &Document, sections: &[SectionInfo]) -> Vec<Provenance> {
+    let mut provenance = vec![];
+    for (section_index, (content, section)) in doc.contents.iter().zip(sections).enumerate() {
+        for segment in &section.segments {
+            let end = segment.end.min(content.len());
+            let start = segment.start.min(end);
+            if !content.is_char_boundary(start) || !content.is_char_boundary(end) {
+                continue;
+            }
+            let text = &content[start..end];
+            let trimmed = text.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let start = start + (text.len() - text.trim_start().len());
+            let chars_start = content[..start].chars().count();
+            provenance.push(Provenance {
+                section_index,
+                start: chars_start,
+                end: chars_start + trimmed.chars().count(),
+                source_start: segment.source_start,
+                source_end: segment.source_end,
+            });
+        }
+    }
+    provenance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+
+    fn segment(start: usize, end: usize, source_start: usize, source_end: usize) -> Segment {
+        Segment {
+            start,
+            end,
+            source_start,
+            source_end,
+        }
+    }
+
+    #[test]
+    fn node_ranges() {
+        let wikitext = "東京は[[日本]]の首都。";
+        let result = create_ja_config().parse(wikitext);
+        let ranges: Vec<&str> = result
+            .nodes
+            .iter()
+            .map(|node| {
+                let (start, end) = node_range(node);
+                &wikitext[start..end]
+            })
+            .collect();
+        assert_eq!(ranges, vec!["東京は", "[[日本]]", "の首都。"]);
+    }
+
+    #[test]
+    fn character_ranges() {
+        let doc = Document {
+            contents: vec![String::from("リード"), String::from("歴史\n 東京は 日本")],
+            ..Default::default()
+        };
+        let mut lead = SectionInfo::new(0, &doc);
+        lead.segments.push(segment(0, 9, 0, 9));
+        let mut history = SectionInfo::new(2, &doc);
+        let content = doc.contents[1].as_str();
+        let text_start = content.find(' ').unwrap();
+        history.segments = vec![
+            // heading
+            segment(0, 6, 20, 32),
+            // " 東京は " with spaces around it
+            segment(text_start, text_start + 11, 33, 42),
+            // a line break only
+            segment(6, 7, 32, 33),
+            // " 日本" and past the end of the content
+            segment(17, 30, 50, 60),
+        ];
+        let provenance: Vec<(usize, usize, usize, usize, usize)> =
+            parse_provenance(&doc, &[lead, history])
+                .into_iter()
+                .map(|p| {
+                    (
+                        p.section_index,
+                        p.start,
+                        p.end,
+                        p.source_start,
+                        p.source_end,
+                    )
+                })
+                .collect();
+        assert_eq!(
+            provenance,
+            vec![
+                (0, 0, 3, 0, 9),
+                (1, 0, 2, 20, 32),
+                (1, 4, 7, 33, 42),
+                (1, 8, 10, 50, 60),
+            ]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::parser::common_parser::{parse_text_only, visit_nodes};
+use crate::parser::model::Reading;
+use crate::parser::template_parser::{get_param, TemplateRule, TemplateRules};
+use log::debug;
+use parse_wiki_text::Node;
+
+// {{DEFAULTSORT:やまだ たろう}} and its aliases
+const DEFAULTSORT: &[&str] = &[
+    "DEFAULTSORT",
+    "DEFAULTSORTKEY",
+    "DEFAULTCATEGORYSORT",
+    "デフォルトソート",
+];
+
+// Characters between the words of a reading such as やまだ たろう or やまだ・たろう
+const SEPARATORS: &[char] = &[' ', '\u{3000}', '・', '、', ',', '，', '=', '＝'];
+
+// Readings of the title in {{DEFAULTSORT}} and the sort keys of the categories,
+// and readings of the templates with the ruby rule such as {{読み仮名|東京|とうきょう}}.
+// Sort keys not written in kana are skipped.
+pub fn parse_readings(rules: &TemplateRules, title: &str, nodes: &[Node]) -> Vec<Reading> {
+    // 山田太郎 (俳優) is read as 山田太郎
+    let title = match title.rsplit_once(" (") {
+        Some((base, _)) if title.ends_with(')') => base,
+        _ => title,
+    };
+    let mut readings = vec![];
+    visit_nodes(nodes, &mut |node| match node {
+        Node::Template {
+            name, parameters, ..
+        } => {
+            let name = parse_text_only(name).trim().to_string();
+            if let Some((magic_word, key)) = name.split_once(':') {
+                if DEFAULTSORT.contains(&magic_word.trim().to_uppercase().as_str()) {
+                    push_reading(&mut readings, title, key, "DEFAULTSORT");
+                    return;
+                }
+            }
+            if let Some(TemplateRule::Ruby { .. }) = rules.get(name.as_str()) {
+                let text = get_param(parameters, 1).unwrap_or_default();
+                let reading = get_param(parameters, 2).unwrap_or_default();
+                push_reading(
+                    &mut readings,
+                    text.as_str(),
+                    reading.as_str(),
+                    name.as_str(),
+                );
+            }
+        }
+        Node::Category { ordinal, .. } if !ordinal.is_empty() => {
+            push_reading(&mut readings, title, &parse_text_only(ordinal), "category")
+        }
+        _ => {}
+    });
+    readings
+}
+
+// Add the reading if it is kana and the same reading of the text is not added yet
+pub fn push_reading(readings: &mut Vec<Reading>, text: &str, reading: &str, source: &str) {
+    let text = text.trim();
+    if text.is_empty() || reading.trim().is_empty() {
+        return;
+    }
+    let hiragana = match normalize_reading(reading) {
+        Some(hiragana) => hiragana,
+        None => {
+            debug!("[WARN] Reading is not kana... [{}] [{}]", text, reading);
+            return;
+        }
+    };
+    if readings
+        .iter()
+        .any(|r| r.text == text && r.hiragana == hiragana)
+    {
+        return;
+    }
+    readings.push(Reading {
+        text: text.to_string(),
+        katakana: hiragana.chars().map(to_katakana).collect(),
+        hiragana,
+        source: source.to_string(),
+    });
+}
+
+// The reading in hiragana without separators, None if it has other than kana
+fn normalize_reading(reading: &str) -> Option<String> {
+    let hiragana: String = reading
+        .chars()
+        .filter(|c| !c.is_whitespace() && !SEPARATORS.contains(c))
+        .map(to_hiragana)
+        .collect();
+    let is_kana = |c: char| matches!(c, 'ぁ'..='ゖ' | 'ゝ' | 'ゞ' | 'ー' | 'ヷ'..='ヺ');
+    if !hiragana.is_empty() && hiragana.chars().all(is_kana) {
+        Some(hiragana)
+    } else {
+        None
+    }
+}
+
+// ァ..ヶ and ヽヾ are shifted to ぁ..ゖ and ゝゞ. ヷ..ヺ have no hiragana.
+fn to_hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' | 'ヽ' | 'ヾ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn to_katakana(c: char) -> char {
+    match c {
+        'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+
+    fn readings(title: &str, wikitext: &str) -> Vec<(String, String, String, String)> {
+        let rules = TemplateRules::default();
+        let result = create_ja_config().parse(wikitext);
+        parse_readings(&rules, title, &result.nodes)
+            .into_iter()
+            .map(|r| (r.text, r.hiragana, r.katakana, r.source))
+            .collect()
+    }
+
+    fn reading(
+        text: &str,
+        hiragana: &str,
+        katakana: &str,
+        source: &str,
+    ) -> (String, String, String, String) {
+        (
+            text.to_string(),
+            hiragana.to_string(),
+            katakana.to_string(),
+            source.to_string(),
+        )
+    }
+
+    #[test]
+    fn defaultsort_and_sort_keys() {
+        assert_eq!(
+            readings(
+                "山田太郎 (俳優)",
+                "{{DEFAULTSORT:やまだ たろう}}\n[[Category:日本の俳優|やまた たろう]]\n[[Category:存命人物]]"
+            ),
+            vec![
+                reading("山田太郎", "やまだたろう", "ヤマダタロウ", "DEFAULTSORT"),
+                reading("山田太郎", "やまたたろう", "ヤマタタロウ", "category"),
+            ]
+        );
+        // the same reading is added once
+        assert_eq!(
+            readings(
+                "東京",
+                "{{デフォルトソート:トウキョウ}}[[Category:都市|とうきょう]]"
+            )
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn ruby_templates() {
+        assert_eq!(
+            readings(
+                "東京",
+                "{{読み仮名|東京|とうきょう}}、{{ルビ|漢字|かん・じ}}"
+            ),
+            vec![
+                reading("東京", "とうきょう", "トウキョウ", "読み仮名"),
+                reading("漢字", "かんじ", "カンジ", "ルビ"),
+            ]
+        );
+    }
+
+    #[test]
+    fn not_kana() {
+        assert!(readings("Tokyo", "{{DEFAULTSORT:Tokyo}}[[Category:都市|*]]").is_empty());
+        assert!(readings("東京", "{{読み仮名|東京|Tōkyō}}").is_empty());
+    }
+
+    #[test]
+    fn kana_conversion() {
+        assert_eq!(
+            normalize_reading("ヴァイオリン").as_deref(),
+            Some("ゔぁいおりん")
+        );
+        assert_eq!(normalize_reading("ヷ").as_deref(), Some("ヷ"));
+        assert_eq!(to_katakana('ゔ'), 'ヴ');
+    }
+}
@@ -0,0 +1,325 @@
+use crate::parser::citation_parser::parse_citation;
+use crate::parser::common_parser::{
+    parse_attribute, parse_inline_text, parse_text_only, visit_nodes,
+};
+use crate::parser::model::{Citation, Footnote, Reference};
+use crate::parser::template_parser::{TemplateContext, TemplateRule};
+use parse_wiki_text::{DefinitionListItem, ListItem, Node, Parameter};
+use std::collections::HashMap;
+
+// Collect <ref> tags of the page text, list items, template parameters and tables,
+// in the order they are written. A reused named ref points to the same reference,
+// its content may be defined in <references>.
+// One footnote is returned per <ref> tag, its position is set by strip_annotations.
+// Citation templates in the refs, the page text and list items are also collected.
+pub fn parse_references(
+    context: &TemplateContext,
+    nodes: &[Node],
+    wikitext: &str,
+) -> (Vec<Reference>, Vec<Footnote>, Vec<Citation>) {
+    let mut parser = ReferenceParser {
+        context,
+        wikitext,
+        references: vec![],
+        footnotes: vec![],
+        citations: vec![],
+        names: HashMap::new(),
+        section_index: 0,
+    };
+    parser.parse_nodes(nodes);
+    (parser.references, parser.footnotes, parser.citations)
+}
+
+struct ReferenceParser<'a> {
+    context: &'a TemplateContext<'a>,
+    wikitext: &'a str,
+    references: Vec<Reference>,
+    footnotes: Vec<Footnote>,
+    citations: Vec<Citation>,
+    // (group, name) to reference index
+    names: HashMap<(Option<String>, String), usize>,
+    // counted the same way as the sections of main
+    section_index: usize,
+}
+
+impl ReferenceParser<'_> {
+    // only nodes written by main and list_parser, so footnotes follow the text order
+    fn parse_nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Tag {
+                    name,
+                    nodes,
+                    start,
+                    end,
+                } if name == "ref" => self.parse_ref(nodes, *start, *end),
+                Node::Tag {
+                    name,
+                    nodes,
+                    start,
+                    end,
+                } if name == "references" => self.parse_reference_list(nodes, *start, *end),
+                Node::Heading { .. } => self.section_index += 1,
+                Node::Template {
+                    name, parameters, ..
+                } => {
+                    self.parse_citation(name, parameters, None);
+                    self.parse_nested_refs(node);
+                }
+                Node::Table { .. } => self.parse_nested_refs(node),
+                Node::UnorderedList { items, .. } | Node::OrderedList { items, .. } => {
+                    self.parse_items(items)
+                }
+                Node::DefinitionList { items, .. } => self.parse_definition_items(items),
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_nested_refs(&mut self, node: &Node) {
+        for tag in nested_refs(node) {
+            if let Node::Tag {
+                nodes, start, end, ..
+            } = tag
+            {
+                self.parse_ref(nodes, *start, *end);
+            }
+        }
+    }
+
+    fn parse_items(&mut self, items: &[ListItem]) {
+        for item in items {
+            self.parse_nodes(&item.nodes);
+        }
+    }
+
+    fn parse_definition_items(&mut self, items: &[DefinitionListItem]) {
+        for item in items {
+            self.parse_nodes(&item.nodes);
+        }
+    }
+
+    fn parse_ref(&mut self, nodes: &[Node], start: usize, end: usize) {
+        let reference_index = self.add_reference(nodes, start, end, None);
+        self.footnotes.push(Footnote {
+            reference_index,
+            section_index: 0,
+            offset: 0,
+        });
+    }
+
+    // <references group="b"><ref name="a">...</ref></references>
+    // The refs define the content of named refs in the text, they are not footnotes.
+    fn parse_reference_list(&mut self, nodes: &[Node], start: usize, end: usize) {
+        let source = self.wikitext.get(start..end).unwrap_or("");
+        let attributes = source
+            .split_once('>')
+            .map(|(tag, _)| tag.trim_start_matches("<references").trim_end_matches('/'))
+            .unwrap_or("");
+        let group = parse_attribute(attributes, "group").filter(|g| !g.is_empty());
+        for node in nodes {
+            if let Node::Tag {
+                name,
+                nodes,
+                start,
+                end,
+            } = node
+            {
+                if name == "ref" {
+                    self.add_reference(nodes, *start, *end, group.clone());
+                }
+            }
+        }
+    }
+
+    // returns the index of the reference, a named ref reuses the reference of the name
+    fn add_reference(
+        &mut self,
+        nodes: &[Node],
+        start: usize,
+        end: usize,
+        list_group: Option<String>,
+    ) -> usize {
+        let source = self.wikitext.get(start..end).unwrap_or("");
+        // <ref name="a" group="b"> or <ref name="a" />
+        let (attributes, content) = match source.split_once('>') {
+            Some((tag, rest)) => (
+                tag.trim_start_matches("<ref").trim_end_matches('/'),
+                rest.rsplit_once("</")
+                    .map(|(content, _)| content)
+                    .unwrap_or(""),
+            ),
+            None => ("", ""),
+        };
+        let name = parse_attribute(attributes, "name").filter(|n| !n.is_empty());
+        let group = parse_attribute(attributes, "group")
+            .filter(|g| !g.is_empty())
+            .or(list_group);
+
+        let mut text = String::new();
+        let mut links = vec![];
+        parse_inline_text(self.context, nodes, &mut text, &mut links);
+        let reference = Reference {
+            name: name.clone(),
+            group: group.clone(),
+            text: text.split_whitespace().collect::<Vec<&str>>().join(" "),
+            links,
+            wikitext: content.trim().to_string(),
+        };
+
+        // citations are taken from the ref with the content
+        let (reference_index, content) = match name {
+            Some(name) => match self.names.get(&(group.clone(), name.clone())) {
+                Some(index) => {
+                    // a reused ref may come before the ref with the content
+                    if !reference.wikitext.is_empty() && self.references[*index].wikitext.is_empty()
+                    {
+                        self.references[*index] = reference;
+                        (*index, true)
+                    } else {
+                        (*index, false)
+                    }
+                }
+                None => {
+                    self.names.insert((group, name), self.references.len());
+                    self.references.push(reference);
+                    (self.references.len() - 1, true)
+                }
+            },
+            None => {
+                self.references.push(reference);
+                (self.references.len() - 1, true)
+            }
+        };
+        if content {
+            for node in nodes {
+                if let Node::Template {
+                    name, parameters, ..
+                } = node
+                {
+                    self.parse_citation(name, parameters, Some(reference_index));
+                }
+            }
+        }
+        reference_index
+    }
+
+    fn parse_citation(
+        &mut self,
+        name: &[Node],
+        parameters: &[Parameter],
+        reference_index: Option<usize>,
+    ) {
+        if let Some(TemplateRule::Citation) = self.context.rules.get(&parse_text_only(name)) {
+            let mut citation = parse_citation(name, parameters);
+            citation.reference_index = reference_index;
+            citation.section_index = self.section_index;
+            self.citations.push(citation);
+        }
+    }
+}
+
+// <ref> tags in the parameters of a template or in the cells of a table.
+// They have no place in the text, so main and list_parser put their footnote markers
+// after the text of the template or the table.
+pub fn nested_refs<'a>(node: &'a Node<'a>) -> Vec<&'a Node<'a>> {
+    let mut refs = vec![];
+    if let Node::Template { .. } | Node::Table { .. } = node {
+        visit_nodes(std::slice::from_ref(node), &mut |node| {
+            if let Node::Tag { name, .. } = node {
+                if name == "ref" {
+                    refs.push(node);
+                }
+            }
+        });
+    }
+    refs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+    use crate::parser::template_parser::TemplateRules;
+
+    fn references(wikitext: &str) -> (Vec<Reference>, Vec<Footnote>, Vec<Citation>) {
+        let rules = TemplateRules::default();
+        let context = TemplateContext {
+            rules: &rules,
+            today: None,
+        };
+        let result = create_ja_config().parse(wikitext);
+        parse_references(&context, &result.nodes, wikitext)
+    }
+
+    fn reference_indexes(footnotes: &[Footnote]) -> Vec<usize> {
+        footnotes.iter().map(|f| f.reference_index).collect()
+    }
+
+    #[test]
+    fn named_refs() {
+        let (references, footnotes, _) = references(
+            "本文<ref name=\"a\">[[出典]]の本</ref>。\
+             別<ref>注</ref>。再<ref name=a />、別グループ<ref name=\"a\" group=\"注\">注釈</ref>",
+        );
+        assert_eq!(references.len(), 3);
+        assert_eq!(references[0].name.as_deref(), Some("a"));
+        assert_eq!(references[0].text, "出典の本");
+        assert_eq!(references[0].wikitext, "[[出典]]の本");
+        assert_eq!(references[0].links.len(), 1);
+        assert_eq!(references[2].group.as_deref(), Some("注"));
+        assert_eq!(reference_indexes(&footnotes), vec![0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn reused_ref_before_content() {
+        let (references, footnotes, _) =
+            references("前<ref name=\"b\" />、後<ref name=\"b\">内容</ref>");
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].text, "内容");
+        assert_eq!(reference_indexes(&footnotes), vec![0, 0]);
+    }
+
+    #[test]
+    fn refs_defined_in_references() {
+        let (references, footnotes, citations) = references(
+            "本文<ref name=\"x\" />、注<ref name=\"y\" group=\"注\" />\n\
+             <references>\n<ref name=\"x\">ウェブ{{Cite web|title=出典}}</ref>\n\
+             <ref name=\"z\">未使用</ref>\n</references>\n\
+             <references group=\"注\">\n<ref name=\"y\">注釈</ref>\n</references>",
+        );
+        let texts: Vec<&str> = references.iter().map(|r| r.text.as_str()).collect();
+        assert_eq!(texts, vec!["ウェブ", "注釈", "未使用"]);
+        assert_eq!(references[0].wikitext, "ウェブ{{Cite web|title=出典}}");
+        assert_eq!(references[1].group.as_deref(), Some("注"));
+        // the refs in the list are not footnotes
+        assert_eq!(reference_indexes(&footnotes), vec![0, 1]);
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].reference_index, Some(0));
+    }
+
+    #[test]
+    fn nested() {
+        let wikitext = "{{基礎情報 人物|名前=太郎<ref>A</ref>}}\n\
+                        {|\n| セル<ref>B</ref>\n|}\n\
+                        * 項目<ref>C</ref>\n本文<ref>D</ref>";
+        let (references, footnotes, _) = references(wikitext);
+        let texts: Vec<&str> = references.iter().map(|r| r.text.as_str()).collect();
+        assert_eq!(texts, vec!["A", "B", "C", "D"]);
+        assert_eq!(reference_indexes(&footnotes), vec![0, 1, 2, 3]);
+        let result = create_ja_config().parse(wikitext);
+        assert_eq!(nested_refs(&result.nodes[0]).len(), 1);
+    }
+
+    #[test]
+    fn citations() {
+        let (_, _, citations) =
+            references("本文<ref>{{Cite web|title=出典}}</ref>\n== 節 ==\n{{Cite book|title=本}}");
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[0].title.as_deref(), Some("出典"));
+        assert_eq!(citations[0].reference_index, Some(0));
+        assert_eq!(citations[0].section_index, 0);
+        assert_eq!(citations[1].reference_index, None);
+        assert_eq!(citations[1].section_index, 1);
+    }
+}
@@ -0,0 +1,81 @@
+// characters that end a sentence
+const SENTENCE_END: &[char] = &['。', '！', '？', '!', '?'];
+// closing brackets of quotes and parentheses
+const CLOSING: &[char] = &['」', '』', '）', ')', '】', '"', '”'];
+
+// Split text into sentences after 。！？ and line breaks.
+// 。！？ before a closing bracket end the quote, not the sentence: 「そうか。」と言った。
+// Sentences are trimmed and empty ones are dropped.
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let end = if SENTENCE_END.contains(&c) {
+            let mut end = index + c.len_utf8();
+            let mut quoted = false;
+            while let Some(&(next_index, next)) = chars.peek() {
+                if SENTENCE_END.contains(&next) || CLOSING.contains(&next) {
+                    quoted |= CLOSING.contains(&next);
+                    end = next_index + next.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if quoted {
+                continue;
+            }
+            end
+        } else if c == '\n' {
+            index
+        } else {
+            continue;
+        };
+        push_sentence(&mut sentences, &text[start..end]);
+        start = end;
+    }
+    push_sentence(&mut sentences, &text[start..]);
+    sentences
+}
+
+fn push_sentence<'a>(sentences: &mut Vec<&'a str>, sentence: &'a str) {
+    let sentence = sentence.trim();
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentences() {
+        assert_eq!(
+            split_sentences("東京は首都である。人口は多い！本当？"),
+            vec!["東京は首都である。", "人口は多い！", "本当？"]
+        );
+        assert_eq!(
+            split_sentences("「そうか。」と言った。Really?! Yes."),
+            vec!["「そうか。」と言った。", "Really?!", "Yes."]
+        );
+        assert_eq!(
+            split_sentences("彼は「行こう！」と言い（本当？）、出かけた。"),
+            vec!["彼は「行こう！」と言い（本当？）、出かけた。"]
+        );
+        assert_eq!(
+            split_sentences("「そうか。」\n次。"),
+            vec!["「そうか。」", "次。"]
+        );
+    }
+
+    #[test]
+    fn line_breaks_and_spaces() {
+        assert_eq!(
+            split_sentences("  見出し\n本文（注釈。）\n\n終わり"),
+            vec!["見出し", "本文（注釈。）", "終わり"]
+        );
+        assert!(split_sentences(" \n ").is_empty());
+    }
+}
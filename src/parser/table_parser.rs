@@ -0,0 +1,255 @@
+use crate::parser::common_parser::{parse_attribute, parse_inline_text, parse_text_only};
+use crate::parser::model::{Table, TableCell};
+use crate::parser::template_parser::TemplateContext;
+use log::warn;
+use parse_wiki_text::{Node, TableCaption, TableCellType, TableRow};
+use std::str::FromStr;
+
+// rowspan and colspan larger than this are cut
+const MAX_SPAN: usize = 100;
+
+// How tables are written into the page text
+pub enum TableFormat {
+    Tsv,
+    Markdown,
+}
+
+impl FromStr for TableFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tsv" => Ok(TableFormat::Tsv),
+            "markdown" => Ok(TableFormat::Markdown),
+            _ => {
+                warn!("Unexpected Table Format: [{}]", s);
+                Err(())
+            }
+        }
+    }
+}
+
+// Rows of the table with rowspan and colspan expanded.
+// A spanned cell is copied to every position it covers.
+pub fn parse_table(
+    context: &TemplateContext,
+    section_index: usize,
+    captions: &[TableCaption],
+    rows: &[TableRow],
+) -> Table {
+    let mut expanded_rows = vec![];
+    // (remaining rows, cell) per column for cells spanning down
+    let mut spans: Vec<Option<(usize, TableCell)>> = vec![];
+    for row in rows {
+        let mut cells = vec![];
+        for cell in &row.cells {
+            fill_spans(&mut spans, &mut cells, false);
+            let attributes = cell
+                .attributes
+                .as_ref()
+                .map(|nodes| parse_text_only(nodes))
+                .unwrap_or_default();
+            let rowspan = parse_span(attributes.as_str(), "rowspan");
+            let colspan = parse_span(attributes.as_str(), "colspan");
+            let parsed = parse_cell(context, &cell.content, cell.type_ == TableCellType::Heading);
+            for _ in 0..colspan {
+                if rowspan > 1 {
+                    if spans.len() <= cells.len() {
+                        spans.resize(cells.len() + 1, None);
+                    }
+                    spans[cells.len()] = Some((rowspan - 1, parsed.clone()));
+                }
+                cells.push(parsed.clone());
+            }
+        }
+        fill_spans(&mut spans, &mut cells, true);
+        if !cells.is_empty() {
+            expanded_rows.push(cells);
+        }
+    }
+    let header_rows = expanded_rows
+        .iter()
+        .take_while(|cells| cells.iter().all(|cell| cell.header))
+        .count();
+    Table {
+        section_index,
+        captions: captions
+            .iter()
+            .map(|caption| parse_cell(context, &caption.content, false).text)
+            .filter(|caption| !caption.is_empty())
+            .collect(),
+        header_rows,
+        rows: expanded_rows,
+    }
+}
+
+// Add cells spanning down from the rows above at the current column.
+// At the end of a row all remaining spanned columns are added, with empty cells for gaps.
+fn fill_spans(
+    spans: &mut [Option<(usize, TableCell)>],
+    cells: &mut Vec<TableCell>,
+    end_of_row: bool,
+) {
+    while cells.len() < spans.len() {
+        let column = cells.len();
+        match spans[column].take() {
+            Some((remaining, cell)) => {
+                cells.push(cell.clone());
+                if remaining > 1 {
+                    spans[column] = Some((remaining - 1, cell));
+                }
+            }
+            None if end_of_row && spans[column..].iter().any(|s| s.is_some()) => {
+                cells.push(TableCell {
+                    text: String::new(),
+                    header: false,
+                    links: vec![],
+                });
+            }
+            None => break,
+        }
+    }
+}
+
+// rowspan="2", rowspan=2 or rowspan='2'
+fn parse_span(attributes: &str, name: &str) -> usize {
+    parse_attribute(attributes, name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1)
+        .clamp(1, MAX_SPAN)
+}
+
+// cell text on one line and the links in it
+fn parse_cell(context: &TemplateContext, nodes: &[Node], header: bool) -> TableCell {
+    let mut text = String::new();
+    let mut links = vec![];
+    parse_inline_text(context, nodes, &mut text, &mut links);
+    TableCell {
+        text: text.split_whitespace().collect::<Vec<&str>>().join(" "),
+        header,
+        links,
+    }
+}
+
+// Write the table as text. Captions come first, one per line.
+pub fn linearize_table(table: &Table, format: &TableFormat) -> String {
+    let mut lines: Vec<String> = table.captions.to_vec();
+    match format {
+        TableFormat::Tsv => {
+            for cells in &table.rows {
+                let cells: Vec<String> = cells.iter().map(|c| c.text.replace('\t', " ")).collect();
+                lines.push(cells.join("\t"));
+            }
+        }
+        TableFormat::Markdown => {
+            let columns = table
+                .rows
+                .iter()
+                .map(|cells| cells.len())
+                .max()
+                .unwrap_or(0);
+            for (index, cells) in table.rows.iter().enumerate() {
+                let mut line = String::from("|");
+                for column in 0..columns {
+                    let text = cells.get(column).map(|c| c.text.as_str()).unwrap_or("");
+                    line.push(' ');
+                    line.push_str(text.replace('|', "\\|").as_str());
+                    line.push_str(" |");
+                }
+                lines.push(line);
+                if index == 0 {
+                    lines.push(format!("|{}", " --- |".repeat(columns)));
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_ja_config;
+    use crate::parser::template_parser::TemplateRules;
+
+    fn table(wikitext: &str) -> Table {
+        let rules = TemplateRules::default();
+        let context = TemplateContext {
+            rules: &rules,
+            today: None,
+        };
+        let result = create_ja_config().parse(wikitext);
+        match result.nodes.first() {
+            Some(Node::Table { captions, rows, .. }) => parse_table(&context, 0, captions, rows),
+            _ => panic!("no table in [{}]", wikitext),
+        }
+    }
+
+    fn texts(table: &Table) -> Vec<Vec<&str>> {
+        table
+            .rows
+            .iter()
+            .map(|cells| cells.iter().map(|cell| cell.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn header_and_caption() {
+        let table = table("{|\n|+ 人口\n! 都市 !! 人口\n|-\n| [[東京]] || 1400万\n|}");
+        assert_eq!(table.captions, vec!["人口"]);
+        assert_eq!(table.header_rows, 1);
+        assert_eq!(
+            texts(&table),
+            vec![vec!["都市", "人口"], vec!["東京", "1400万"]]
+        );
+        assert_eq!(table.rows[1][0].links.len(), 1);
+    }
+
+    #[test]
+    fn rowspan() {
+        let table = table("{|\n| rowspan=\"2\" | A || B\n|-\n| C\n|-\n| D || E\n|}");
+        assert_eq!(
+            texts(&table),
+            vec![vec!["A", "B"], vec!["A", "C"], vec!["D", "E"]]
+        );
+    }
+
+    #[test]
+    fn colspan() {
+        let table = table("{|\n| colspan=2 | A || B\n|-\n| C || D || E\n|}");
+        assert_eq!(
+            texts(&table),
+            vec![vec!["A", "A", "B"], vec!["C", "D", "E"]]
+        );
+    }
+
+    #[test]
+    fn rowspan_and_colspan() {
+        let table = table("{|\n| rowspan='2' colspan='2' | A || B\n|-\n| C\n|}");
+        assert_eq!(
+            texts(&table),
+            vec![vec!["A", "A", "B"], vec!["A", "A", "C"]]
+        );
+    }
+
+    #[test]
+    fn rowspan_in_a_later_column() {
+        // the second row has no cell for the first column
+        let table = table("{|\n| A || rowspan=2 | B\n|-\n|}");
+        assert_eq!(texts(&table), vec![vec!["A", "B"], vec!["", "B"]]);
+    }
+
+    #[test]
+    fn linearize() {
+        let mut table = table("{|\n|+ 表\n! a !! b\n|-\n| 1 || x\n|}");
+        table.rows[1][1].text = String::from("x|y");
+        assert_eq!(
+            linearize_table(&table, &TableFormat::Tsv),
+            "表\na\tb\n1\tx|y"
+        );
+        assert_eq!(
+            linearize_table(&table, &TableFormat::Markdown),
+            "表\n| a | b |\n| --- | --- |\n| 1 | x\\|y |"
+        );
+    }
+}
@@ -0,0 +1,842 @@
+use crate::parser::parser_function::{
+    error_text, evaluate_expression, format_time, is_error, values_equal, DateTime,
+};
+use crate::parser::template_parser::{normalize_template_name, TemplateRules};
+use log::debug;
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub struct ExpandSettings {
+    // templates nested deeper than this are not expanded
+    pub max_depth: usize,
+    // bytes of text expanded in templates per page, the rest is not expanded
+    pub max_size: usize,
+}
+
+impl Default for ExpandSettings {
+    fn default() -> Self {
+        ExpandSettings {
+            max_depth: 20,
+            max_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+// Expand transclusions with the wikitext of Template pages in the dump.
+// Templates that have a template rule are kept for the rule.
+pub struct TemplateExpander<'a> {
+    // normalized template name to the text used for transclusion
+    templates: HashMap<String, String>,
+    settings: ExpandSettings,
+    rules: &'a TemplateRules,
+    // the time of the dump, the current time for #time and {{CURRENTYEAR}}
+    timestamp: String,
+}
+
+impl<'a> TemplateExpander<'a> {
+    pub fn new(settings: ExpandSettings, rules: &'a TemplateRules) -> Self {
+        TemplateExpander {
+            templates: HashMap::new(),
+            settings,
+            rules,
+            timestamp: String::new(),
+        }
+    }
+
+    // The dump timestamp is the newest revision timestamp in the dump,
+    // called with the timestamp of every page in the dump.
+    pub fn add_timestamp(&mut self, timestamp: &str) {
+        if timestamp > self.timestamp.as_str() {
+            self.timestamp = timestamp.to_string();
+        }
+    }
+
+    pub fn timestamp(&self) -> &str {
+        self.timestamp.as_str()
+    }
+
+    // title is "Template:Name"
+    pub fn add_template(&mut self, title: &str, wikitext: &str) {
+        self.templates
+            .insert(normalize_template_name(title), transclusion_text(wikitext));
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    // The expanded page and where the expanded parts come from.
+    // title is for {{PAGENAME}}, the dump timestamp is the current time for #time.
+    pub fn expand_page(&self, wikitext: &str, title: &str) -> (String, SourceMap) {
+        let mut expansion = Expansion {
+            expander: self,
+            size: 0,
+            title,
+        };
+        let mut source_map = SourceMap::default();
+        let text = expansion.expand(wikitext, None, 0, &mut source_map.replacements);
+        (text, source_map)
+    }
+
+    fn find_template(&self, name: &str) -> Option<&str> {
+        let name = name.trim();
+        let name = name
+            .strip_prefix("subst:")
+            .or_else(|| name.strip_prefix("safesubst:"))
+            .unwrap_or(name);
+        // parser functions and transclusion of main namespace pages
+        if name.is_empty() || name.starts_with('#') || name.starts_with(':') {
+            return None;
+        }
+        if self.rules.get(name).is_some() {
+            return None;
+        }
+        let mut key = normalize_template_name(name);
+        // follow redirects between templates
+        for _ in 0..5 {
+            let text = self.templates.get(&key)?;
+            match redirect_target(text) {
+                Some(target) => key = normalize_template_name(target),
+                None => return Some(text.as_str()),
+            }
+        }
+        None
+    }
+}
+
+// Expanded text [start, end) replaced the page wikitext [source_start, source_end)
+struct Replacement {
+    start: usize,
+    end: usize,
+    source_start: usize,
+    source_end: usize,
+}
+
+// Map byte offsets in the expanded page back to the page wikitext
+#[derive(Default)]
+pub struct SourceMap {
+    replacements: Vec<Replacement>,
+}
+
+impl SourceMap {
+    // text from an expanded template maps to the whole template
+    pub fn source_range(&self, start: usize, end: usize) -> (usize, usize) {
+        (
+            self.source_offset(start, false),
+            self.source_offset(end, true),
+        )
+    }
+
+    fn source_offset(&self, offset: usize, end: bool) -> usize {
+        let replacement = self
+            .replacements
+            .iter()
+            .take_while(|r| {
+                if end {
+                    r.start < offset
+                } else {
+                    r.start <= offset
+                }
+            })
+            .last();
+        match replacement {
+            None => offset,
+            Some(r) if offset < r.end || (end && offset == r.end) => {
+                if end {
+                    r.source_end
+                } else {
+                    r.source_start
+                }
+            }
+            Some(r) => r.source_end + (offset - r.end),
+        }
+    }
+}
+
+struct Expansion<'a> {
+    expander: &'a TemplateExpander<'a>,
+    // bytes of text expanded in templates, counted as it is expanded
+    size: usize,
+    title: &'a str,
+}
+
+impl Expansion<'_> {
+    // Replace {{template}} and {{{parameter}}} in text.
+    // args are the parameters of the template being expanded, None for the page.
+    // <nowiki>, <pre> and comments are not expanded. In a template, the rest is not
+    // expanded once max_size bytes have been written.
+    fn expand(
+        &mut self,
+        text: &str,
+        args: Option<&HashMap<String, String>>,
+        depth: usize,
+        replacements: &mut Vec<Replacement>,
+    ) -> String {
+        let max_size = self.expander.settings.max_size;
+        let mut out = String::with_capacity(text.len());
+        let mut position = 0;
+        let mut index = 0;
+        while let Some(found) = text[index..].find(['{', '<']) {
+            let start = index + found;
+            if let Some(end) = protected_end(text, start) {
+                index = end;
+                continue;
+            }
+            if !text[start..].starts_with("{{") {
+                index = start + 1;
+                continue;
+            }
+            if args.is_some() {
+                self.size += start - position;
+                if self.size >= max_size {
+                    debug!("[WARN] Template expansion size limit... [{}]", self.title);
+                    break;
+                }
+            }
+            out.push_str(&text[position..start]);
+            let (open, close, end) = match find_close(text, start) {
+                Some(found) => found,
+                None => {
+                    out.push_str("{{");
+                    position = start + 2;
+                    index = position;
+                    continue;
+                }
+            };
+            let inner = &text[start + open..end - close];
+            let replaced = if open == 3 {
+                self.expand_parameter(inner, args, depth)
+            } else {
+                self.expand_template(inner, args, depth)
+            };
+            let replaced = replaced.unwrap_or_else(|| text[start..end].to_string());
+            // parameters are counted where they are used, templates in their own expansion
+            if args.is_some() && open == 3 {
+                self.size += replaced.len();
+            }
+            if replaced != text[start..end] {
+                replacements.push(Replacement {
+                    start: out.len(),
+                    end: out.len() + replaced.len(),
+                    source_start: start,
+                    source_end: end,
+                });
+            }
+            out.push_str(replaced.as_str());
+            position = end;
+            index = end;
+        }
+        if args.is_some() {
+            self.size += text.len() - position;
+        }
+        out.push_str(&text[position..]);
+        out
+    }
+
+    // {{{name|default}}}, kept as is outside of templates
+    fn expand_parameter(
+        &mut self,
+        inner: &str,
+        args: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> Option<String> {
+        let args = args?;
+        let parts = split_parameters(inner);
+        let name = self.expand(parts[0], Some(args), depth, &mut vec![]);
+        match args.get(name.trim()) {
+            Some(value) => Some(value.to_string()),
+            None => parts
+                .get(1)
+                .map(|default| self.expand(default, Some(args), depth, &mut vec![])),
+        }
+    }
+
+    // None when the template is kept as is
+    fn expand_template(
+        &mut self,
+        inner: &str,
+        args: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> Option<String> {
+        let parts = split_parameters(inner);
+        if let Some(result) = self.call_function(&parts, args, depth) {
+            return Some(result);
+        }
+        let name = self.expand(parts[0], args, depth, &mut vec![]);
+        let expander = self.expander;
+        let body = if depth < expander.settings.max_depth && self.size < expander.settings.max_size
+        {
+            expander.find_template(name.as_str())
+        } else {
+            debug!("[WARN] Template expansion limit... [{}]", name.trim());
+            None
+        };
+        match body {
+            Some(body) => {
+                let mut template_args = HashMap::new();
+                let mut position = 0;
+                for part in &parts[1..] {
+                    match split_named_parameter(part) {
+                        Some((key, value)) => {
+                            let key = self.expand(key, args, depth, &mut vec![]);
+                            let value = self.expand(value, args, depth, &mut vec![]);
+                            template_args.insert(key.trim().to_string(), value.trim().to_string());
+                        }
+                        None => {
+                            position += 1;
+                            let value = self.expand(part, args, depth, &mut vec![]);
+                            template_args.insert(position.to_string(), value);
+                        }
+                    }
+                }
+                Some(self.expand(body, Some(&template_args), depth + 1, &mut vec![]))
+            }
+            None => {
+                // kept for the template rules, the parameters may have templates to expand
+                let expanded: Vec<String> = parts
+                    .iter()
+                    .map(|part| self.expand(part, args, depth, &mut vec![]))
+                    .collect();
+                let kept = format!("{{{{{}}}}}", expanded.join("|"));
+                if kept.len() == inner.len() + 4 && kept[2..kept.len() - 2] == *inner {
+                    None
+                } else {
+                    Some(kept)
+                }
+            }
+        }
+    }
+
+    // Parser functions and magic words, None for other templates.
+    // Branches of #if and #switch are expanded only when they are used.
+    fn call_function(
+        &mut self,
+        parts: &[&str],
+        args: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> Option<String> {
+        let (name, first) = match parts[0].split_once(':') {
+            Some((name, first)) => (name.trim(), first),
+            None if parts.len() == 1 => return self.magic_word(parts[0].trim()),
+            None => return None,
+        };
+        // {{#if: test | then | else}}, the text after : is the first argument
+        let mut raw = vec![first];
+        raw.extend_from_slice(&parts[1..]);
+        let result = match name.to_lowercase().as_str() {
+            "#if" => {
+                let test = self.argument(&raw, 0, args, depth);
+                self.argument(&raw, if test.is_empty() { 2 } else { 1 }, args, depth)
+            }
+            "#ifeq" => {
+                let left = self.argument(&raw, 0, args, depth);
+                let right = self.argument(&raw, 1, args, depth);
+                let index = if values_equal(&left, &right) { 2 } else { 3 };
+                self.argument(&raw, index, args, depth)
+            }
+            "#iferror" => {
+                let test = self.argument(&raw, 0, args, depth);
+                if is_error(&test) {
+                    self.argument(&raw, 1, args, depth)
+                } else if raw.len() > 2 {
+                    self.argument(&raw, 2, args, depth)
+                } else {
+                    test
+                }
+            }
+            "#switch" => self.switch(&raw, args, depth),
+            "#expr" => {
+                let expression = self.argument(&raw, 0, args, depth);
+                evaluate_expression(&expression).unwrap_or_else(|e| error_text(&e))
+            }
+            "#time" => {
+                let format = self.argument(&raw, 0, args, depth);
+                let date = self.argument(&raw, 1, args, depth);
+                let date = if date.is_empty() || date.eq_ignore_ascii_case("now") {
+                    DateTime::parse(self.expander.timestamp())
+                } else {
+                    DateTime::parse(&date)
+                };
+                match date {
+                    Some(date) => format_time(&format, &date),
+                    None => error_text("Error: Invalid time."),
+                }
+            }
+            "#tag" => self.tag(&raw, args, depth),
+            "lc" => self.argument(&raw, 0, args, depth).to_lowercase(),
+            "uc" => self.argument(&raw, 0, args, depth).to_uppercase(),
+            "lcfirst" | "ucfirst" => {
+                let text = self.argument(&raw, 0, args, depth);
+                let mut chars = text.chars();
+                match chars.next() {
+                    Some(c) if name.eq_ignore_ascii_case("lcfirst") => {
+                        c.to_lowercase().chain(chars).collect()
+                    }
+                    Some(c) => c.to_uppercase().chain(chars).collect(),
+                    None => text,
+                }
+            }
+            "padleft" | "padright" => {
+                let text = self.argument(&raw, 0, args, depth);
+                let length: usize = self.argument(&raw, 1, args, depth).parse().unwrap_or(0);
+                let pad = match self.argument(&raw, 2, args, depth) {
+                    pad if pad.is_empty() => String::from("0"),
+                    pad => pad,
+                };
+                let count = length.saturating_sub(text.chars().count());
+                let padding: String = pad.chars().cycle().take(count).collect();
+                if name.eq_ignore_ascii_case("padleft") {
+                    padding + text.as_str()
+                } else {
+                    text + padding.as_str()
+                }
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    // Pages are in the main namespace, so PAGENAME is the title.
+    fn magic_word(&self, name: &str) -> Option<String> {
+        let date = DateTime::parse(self.expander.timestamp());
+        let time = |format: &str| date.as_ref().map(|date| format_time(format, date));
+        match name {
+            "PAGENAME" | "FULLPAGENAME" | "BASEPAGENAME" | "SUBPAGENAME" | "ROOTPAGENAME" => {
+                Some(self.title.to_string())
+            }
+            "NAMESPACE" => Some(String::new()),
+            "CURRENTYEAR" | "LOCALYEAR" => time("Y"),
+            "CURRENTMONTH" | "LOCALMONTH" => time("m"),
+            "CURRENTMONTH1" | "LOCALMONTH1" => time("n"),
+            "CURRENTMONTHNAME" | "LOCALMONTHNAME" => time("F"),
+            "CURRENTDAY" | "LOCALDAY" => time("j"),
+            "CURRENTDAY2" | "LOCALDAY2" => time("d"),
+            "CURRENTDOW" | "LOCALDOW" => time("w"),
+            "CURRENTDAYNAME" | "LOCALDAYNAME" => time("l"),
+            "CURRENTTIME" | "LOCALTIME" => time("H:i"),
+            "CURRENTHOUR" | "LOCALHOUR" => time("H"),
+            "CURRENTTIMESTAMP" | "LOCALTIMESTAMP" => time("YmdHis"),
+            "!" => Some(String::from("|")),
+            "=" => Some(String::from("=")),
+            _ => None,
+        }
+    }
+
+    // {{#switch: value | case1 = result1 | case2 | case3 = result23 | #default = result}}
+    // The last case without = is also the default.
+    fn switch(
+        &mut self,
+        raw: &[&str],
+        args: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> String {
+        let value = self.argument(raw, 0, args, depth);
+        let mut matched = false;
+        let mut default = None;
+        for (index, case) in raw.iter().enumerate().skip(1) {
+            match split_named_parameter(case) {
+                Some((key, result)) => {
+                    let key = self.expand(key, args, depth, &mut vec![]);
+                    let key = key.trim();
+                    if matched || values_equal(key, &value) {
+                        return self
+                            .expand(result, args, depth, &mut vec![])
+                            .trim()
+                            .to_string();
+                    }
+                    if key == "#default" {
+                        default = Some(result);
+                    }
+                }
+                None if index == raw.len() - 1 => default = Some(case),
+                None => {
+                    let key = self.expand(case, args, depth, &mut vec![]);
+                    matched = matched || values_equal(key.trim(), &value);
+                }
+            }
+        }
+        default
+            .map(|result| {
+                self.expand(result, args, depth, &mut vec![])
+                    .trim()
+                    .to_string()
+            })
+            .unwrap_or_default()
+    }
+
+    // {{#tag:ref|content|name=a}} is <ref name="a">content</ref>
+    fn tag(
+        &mut self,
+        raw: &[&str],
+        args: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> String {
+        let name = self.argument(raw, 0, args, depth).to_lowercase();
+        let content = raw
+            .get(1)
+            .map(|content| self.expand(content, args, depth, &mut vec![]))
+            .unwrap_or_default();
+        let mut attributes = String::new();
+        for attribute in raw.iter().skip(2) {
+            if let Some((key, value)) = split_named_parameter(attribute) {
+                let key = self.expand(key, args, depth, &mut vec![]);
+                let value = self.expand(value, args, depth, &mut vec![]);
+                let value = value.trim().trim_matches('"').trim_matches('\'');
+                attributes.push_str(format!(" {}=\"{}\"", key.trim(), value).as_str());
+            }
+        }
+        format!("<{0}{1}>{2}</{0}>", name, attributes, content)
+    }
+
+    // the expanded argument without surrounding whitespace, empty if missing
+    fn argument(
+        &mut self,
+        raw: &[&str],
+        index: usize,
+        args: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> String {
+        match raw.get(index) {
+            Some(part) => self
+                .expand(part, args, depth, &mut vec![])
+                .trim()
+                .to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+// (open length, close length, end) of the braces starting at start
+fn find_close(text: &str, start: usize) -> Option<(usize, usize, usize)> {
+    let bytes = text.as_bytes();
+    let open = if bytes[start..].starts_with(b"{{{") {
+        3
+    } else {
+        2
+    };
+    let mut stack = vec![open];
+    let mut index = start + open;
+    while index < bytes.len() {
+        if bytes[index..].starts_with(b"{{{") {
+            stack.push(3);
+            index += 3;
+        } else if bytes[index..].starts_with(b"{{") {
+            stack.push(2);
+            index += 2;
+        } else if bytes[index..].starts_with(b"}}") {
+            let close = match stack.pop() {
+                Some(3) if bytes[index..].starts_with(b"}}}") => 3,
+                _ => 2,
+            };
+            index += close;
+            if stack.is_empty() {
+                return Some((open, close, index));
+            }
+        } else if let Some(end) = protected_end(text, index) {
+            index = end;
+        } else {
+            index += 1;
+        }
+    }
+    None
+}
+
+// The end of <nowiki>...</nowiki>, <pre>...</pre> or <!-- ... --> starting at start.
+// An unclosed comment runs to the end, an unclosed tag is text.
+fn protected_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = &text.as_bytes()[start..];
+    if bytes.starts_with(b"<!--") {
+        return Some(
+            find_ignore_case(text, start + 4, "-->")
+                .map(|end| end + 3)
+                .unwrap_or(text.len()),
+        );
+    }
+    for name in &["nowiki", "pre"] {
+        let name_end = name.len() + 1;
+        let is_tag = bytes.len() > name_end
+            && bytes[0] == b'<'
+            && bytes[1..name_end].eq_ignore_ascii_case(name.as_bytes())
+            && matches!(bytes[name_end], b'>' | b'/' | b' ' | b'\t' | b'\n');
+        if !is_tag {
+            continue;
+        }
+        let open_end = start + text[start..].find('>')? + 1;
+        // <nowiki/>
+        if text[..open_end].ends_with("/>") {
+            return Some(open_end);
+        }
+        let close = format!("</{}>", name);
+        return find_ignore_case(text, open_end, close.as_str()).map(|end| end + close.len());
+    }
+    None
+}
+
+fn find_ignore_case(text: &str, from: usize, pattern: &str) -> Option<usize> {
+    text.as_bytes()[from..]
+        .windows(pattern.len())
+        .position(|window| window.eq_ignore_ascii_case(pattern.as_bytes()))
+        .map(|position| from + position)
+}
+
+// split at | outside of nested templates and links
+fn split_parameters(inner: &str) -> Vec<&str> {
+    let bytes = inner.as_bytes();
+    let mut parts = vec![];
+    let mut braces: usize = 0;
+    let mut links: usize = 0;
+    let mut start = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        match &bytes[index..] {
+            [b'{', b'{', ..] => {
+                braces += 1;
+                index += 2;
+            }
+            [b'}', b'}', ..] => {
+                braces = braces.saturating_sub(1);
+                index += 2;
+            }
+            [b'[', b'[', ..] => {
+                links += 1;
+                index += 2;
+            }
+            [b']', b']', ..] => {
+                links = links.saturating_sub(1);
+                index += 2;
+            }
+            [b'|', ..] if braces == 0 && links == 0 => {
+                parts.push(&inner[start..index]);
+                index += 1;
+                start = index;
+            }
+            [b'<', ..] => index = protected_end(inner, index).unwrap_or(index + 1),
+            _ => index += 1,
+        }
+    }
+    parts.push(&inner[start..]);
+    parts
+}
+
+// name=value with the = outside of nested templates and links
+fn split_named_parameter(part: &str) -> Option<(&str, &str)> {
+    let bytes = part.as_bytes();
+    let mut depth: usize = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        match &bytes[index..] {
+            [b'{', b'{', ..] | [b'[', b'[', ..] => {
+                depth += 1;
+                index += 2;
+            }
+            [b'}', b'}', ..] | [b']', b']', ..] => {
+                depth = depth.saturating_sub(1);
+                index += 2;
+            }
+            [b'=', ..] if depth == 0 => return Some((&part[..index], &part[index + 1..])),
+            [b'<', ..] => index = protected_end(part, index).unwrap_or(index + 1),
+            _ => index += 1,
+        }
+    }
+    None
+}
+
+// #REDIRECT [[Template:Name]] or #転送 [[Template:Name]]
+fn redirect_target(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    let upper = text.get(..9).map(|t| t.to_ascii_uppercase());
+    if upper.as_deref() != Some("#REDIRECT") && !text.starts_with("#転送") {
+        return None;
+    }
+    let (_, target) = text.split_once("[[")?;
+    let (target, _) = target.split_once("]]")?;
+    Some(target.split('#').next().unwrap_or(target))
+}
+
+// The part of a Template page used for transclusion.
+// Only <onlyinclude> if there is one, without <noinclude> and comments.
+fn transclusion_text(wikitext: &str) -> String {
+    let text = if wikitext.contains("<onlyinclude>") {
+        tag_contents(wikitext, "<onlyinclude>", "</onlyinclude>").concat()
+    } else {
+        wikitext.to_string()
+    };
+    let text = remove_sections(text.as_str(), "<noinclude>", "</noinclude>");
+    let text = remove_sections(text.as_str(), "<!--", "-->");
+    text.replace("<includeonly>", "")
+        .replace("</includeonly>", "")
+}
+
+fn tag_contents<'a>(text: &'a str, open: &str, close: &str) -> Vec<&'a str> {
+    let mut contents = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find(open) {
+        rest = &rest[start + open.len()..];
+        let end = rest.find(close).unwrap_or(rest.len());
+        contents.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    contents
+}
+
+// an unclosed section is removed to the end
+fn remove_sections(text: &str, open: &str, close: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(open) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + open.len()..];
+        rest = match rest.find(close) {
+            Some(end) => &rest[end + close.len()..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(templates: &[(&str, &str)], wikitext: &str) -> String {
+        let rules = TemplateRules::default();
+        let mut expander = TemplateExpander::new(ExpandSettings::default(), &rules);
+        expander.add_timestamp("2019-08-14T00:23:07Z");
+        for (title, text) in templates {
+            expander.add_template(title, text);
+        }
+        expander.expand_page(wikitext, "東京").0
+    }
+
+    #[test]
+    fn parameters() {
+        let templates = &[("Template:挨拶", "{{{1}}}、{{{name|名無し}}}さん")];
+        assert_eq!(
+            expand(templates, "{{挨拶|こんにちは}}"),
+            "こんにちは、名無しさん"
+        );
+        assert_eq!(
+            expand(templates, "{{挨拶|name = 太郎 |こんばんは}}"),
+            "こんばんは、太郎さん"
+        );
+        // parameters outside of templates are kept
+        assert_eq!(expand(&[], "{{{1}}}"), "{{{1}}}");
+    }
+
+    #[test]
+    fn transclusion_tags_and_redirects() {
+        let templates = &[
+            (
+                "Template:A",
+                "<noinclude>説明</noinclude>本文<includeonly>だけ</includeonly>",
+            ),
+            ("Template:B", "#REDIRECT [[Template:A]]"),
+            ("Template:C", "前<onlyinclude>中</onlyinclude>後"),
+        ];
+        assert_eq!(expand(templates, "{{A}}"), "本文だけ");
+        assert_eq!(expand(templates, "{{b}}"), "本文だけ");
+        assert_eq!(expand(templates, "{{Template:C}}"), "中");
+        // unknown templates are kept
+        assert_eq!(expand(templates, "{{D|x}}"), "{{D|x}}");
+    }
+
+    #[test]
+    fn template_rules_are_kept() {
+        let templates = &[("Template:Lang", "展開しない"), ("Template:言語", "en")];
+        assert_eq!(
+            expand(templates, "{{lang|{{言語}}|text}}"),
+            "{{lang|en|text}}"
+        );
+    }
+
+    #[test]
+    fn parser_functions() {
+        assert_eq!(expand(&[], "{{#if: x | yes | no }}"), "yes");
+        assert_eq!(expand(&[], "{{#if:  | yes | no }}"), "no");
+        assert_eq!(expand(&[], "{{#ifeq: 1 | 1.0 | same | different}}"), "same");
+        assert_eq!(expand(&[], "{{#switch: b | a = 1 | b | c = 2 | 3}}"), "2");
+        assert_eq!(expand(&[], "{{#switch: z | a = 1 | #default = 3}}"), "3");
+        assert_eq!(expand(&[], "{{#expr: 1 + 2 * 3}}"), "7");
+        assert_eq!(
+            expand(&[], "{{#iferror: {{#expr: 1 / 0}} | error }}"),
+            "error"
+        );
+        assert_eq!(expand(&[], "{{#time: Y年n月j日}}"), "2019年8月14日");
+        assert_eq!(expand(&[], "{{#time: Y | 2000-01-02}}"), "2000");
+        assert_eq!(expand(&[], "{{CURRENTYEAR}} {{PAGENAME}}"), "2019 東京");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let templates = &[("Template:Loop", "x{{Loop}}")];
+        let expanded = expand(templates, "{{Loop}}");
+        assert!(expanded.starts_with("xxx"));
+        assert!(expanded.ends_with("{{Loop}}"));
+    }
+
+    #[test]
+    fn protected_text() {
+        let templates = &[
+            ("Template:T", "展開"),
+            ("Template:Code", "<pre>{{T}}</pre>{{{1}}}<!-- {{T}} -->"),
+        ];
+        assert_eq!(
+            expand(
+                templates,
+                "<nowiki>{{T}}</nowiki> <NOWIKI>{{T}}</NOWIKI> {{T}}"
+            ),
+            "<nowiki>{{T}}</nowiki> <NOWIKI>{{T}}</NOWIKI> 展開"
+        );
+        assert_eq!(
+            expand(templates, "<pre class=\"x\">{{T}}</pre><!-- {{T}} -->{{T}}"),
+            "<pre class=\"x\">{{T}}</pre><!-- {{T}} -->展開"
+        );
+        assert_eq!(expand(templates, "<nowiki/>{{T}}"), "<nowiki/>展開");
+        // an unclosed tag is text, an unclosed comment runs to the end
+        assert_eq!(expand(templates, "<nowiki>{{T}}"), "<nowiki>展開");
+        assert_eq!(expand(templates, "<!-- {{T}}"), "<!-- {{T}}");
+        // in template bodies and parameters
+        assert_eq!(
+            expand(templates, "{{Code|<nowiki>a|b}}</nowiki>}}"),
+            "<pre>{{T}}</pre><nowiki>a|b}}</nowiki>"
+        );
+    }
+
+    #[test]
+    fn size_limit() {
+        let rules = TemplateRules::default();
+        let settings = ExpandSettings {
+            max_depth: 100,
+            max_size: 50,
+        };
+        let mut expander = TemplateExpander::new(settings, &rules);
+        expander.add_template("Template:Loop", "0123456789{{Loop}}{{Loop}}");
+        expander.add_template("Template:T", "展開");
+        let (text, _) = expander.expand_page("{{Loop}} {{T}}", "");
+        // stopped as the text is written, not after the whole recursion
+        assert!(text.starts_with("0123456789"));
+        let written = text.replace("{{Loop}}", "");
+        assert!(written.len() <= 50 + " {{T}}".len());
+        // templates after the limit are kept
+        assert!(text.ends_with(" {{T}}"));
+    }
+
+    #[test]
+    fn source_map() {
+        let rules = TemplateRules::default();
+        let mut expander = TemplateExpander::new(ExpandSettings::default(), &rules);
+        expander.add_template("Template:T", "abcdef");
+        let (text, source_map) = expander.expand_page("12{{T}}34", "");
+        assert_eq!(text, "12abcdef34");
+        // text before and after the template keeps its offset relative to it
+        assert_eq!(source_map.source_range(0, 2), (0, 2));
+        assert_eq!(source_map.source_range(8, 10), (7, 9));
+        // any part of the expanded template maps to the whole template
+        assert_eq!(source_map.source_range(3, 5), (2, 7));
+    }
+}
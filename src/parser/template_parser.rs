@@ -1,49 +1,362 @@
+use crate::parser::citation_parser::is_citation;
 use crate::parser::common_parser::parse_text_only;
+use crate::parser::date_parser::{render_date, render_death_age};
+use crate::parser::infobox_parser::is_infobox;
+use crate::parser::model::DateRole;
+use crate::parser::parser_function::DateTime;
 use log::{debug, trace};
 use parse_wiki_text::{Node, Parameter};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
 
-pub enum TemplateType {
-    Unknown,
+// What is written to the text for a template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum TemplateRule {
+    // nothing
+    Drop,
+    // positional parameter, starts from 1
+    Param {
+        index: usize,
+    },
+    // named parameter
+    Named {
+        name: String,
+    },
+    // parameters joined with the separator, all positional parameters if params is empty
+    Join {
+        #[serde(default)]
+        params: Vec<String>,
+        #[serde(default)]
+        separator: String,
+    },
+    // prefix + parameter + suffix
+    Wrap {
+        #[serde(default = "first_param")]
+        param: String,
+        #[serde(default)]
+        prefix: String,
+        #[serde(default)]
+        suffix: String,
+    },
+    // fixed text in place of the template, `{{name}}` if text is not set
+    Marker {
+        text: Option<String>,
+    },
+    // text in a language, the second parameter or the only one
     Lang,
-    Redirect,
-    Otheruses,
-    TemporaryLink,
-    Unicode,
+    // base text and its reading, the first and the second parameters.
+    // The reading is also written in parentheses if parentheses is set.
+    // written to Document.readings
+    Ruby {
+        #[serde(default)]
+        parentheses: bool,
+    },
+    // written to Document.infobox
+    Infobox,
+    // written to Document.citations
+    Citation,
+    // written to Document.authority_ids
+    AuthorityControl,
+    // written to Document.coordinates
+    Coordinates,
+    // year, month and day parameters as 1950年3月4日, with the age if age is set.
+    // written to Document.dates with the role
+    Date {
+        #[serde(default)]
+        role: DateRole,
+        #[serde(default)]
+        age: bool,
+    },
+    // age at death of the birth date and the death date, 6 parameters.
+    // The death date is also written if date is set.
+    DeathAge {
+        #[serde(default)]
+        death_first: bool,
+        #[serde(default)]
+        date: bool,
+    },
+    // same rule as another template
+    Alias {
+        target: String,
+    },
 }
 
-pub fn parse_template_type(nodes: &Vec<Node>) -> TemplateType {
-    let name = parse_text_only(nodes);
-    let template_type = match name.as_str() {
-        "lang" => TemplateType::Lang,
-        "lang-en" => TemplateType::Lang,
-        "Redirect" => TemplateType::Redirect,
-        "Otheruses" => TemplateType::Otheruses,
-        "仮リンク" => TemplateType::TemporaryLink,
-        "unicode" => TemplateType::Unicode,
-        "IPA" => TemplateType::Unicode,
-        _ => {
-            debug!("[WARN] Unknown Template Type... [{}]", name);
-            TemplateType::Unknown
+fn first_param() -> String {
+    String::from("1")
+}
+
+// Template name to rule. Names are normalized with normalize_template_name.
+pub struct TemplateRules {
+    rules: HashMap<String, TemplateRule>,
+}
+
+// rules for jawiki
+const DEFAULT_RULES: &[(&str, &str)] = &[
+    ("lang", r#"{"rule": "lang"}"#),
+    ("lang-en", r#"{"rule": "lang"}"#),
+    ("lang-ja", r#"{"rule": "lang"}"#),
+    ("lang-zh", r#"{"rule": "lang"}"#),
+    ("lang-ko", r#"{"rule": "lang"}"#),
+    ("lang-fr", r#"{"rule": "lang"}"#),
+    ("lang-de", r#"{"rule": "lang"}"#),
+    ("lang-la", r#"{"rule": "lang"}"#),
+    ("Redirect", r#"{"rule": "drop"}"#),
+    ("Otheruses", r#"{"rule": "drop"}"#),
+    ("仮リンク", r#"{"rule": "param", "index": 1}"#),
+    ("unicode", r#"{"rule": "param", "index": 1}"#),
+    ("IPA", r#"{"rule": "param", "index": 1}"#),
+    ("Nowrap", r#"{"rule": "param", "index": 1}"#),
+    ("ルビ", r#"{"rule": "ruby"}"#),
+    ("Ruby", r#"{"rule": "alias", "target": "ルビ"}"#),
+    ("読み仮名", r#"{"rule": "ruby", "parentheses": true}"#),
+    (
+        "読み仮名 ruby不使用",
+        r#"{"rule": "alias", "target": "読み仮名"}"#,
+    ),
+    ("要出典", r#"{"rule": "drop"}"#),
+    ("Sfn", r#"{"rule": "drop"}"#),
+    ("Reflist", r#"{"rule": "drop"}"#),
+    ("脚注ヘルプ", r#"{"rule": "drop"}"#),
+    ("Main", r#"{"rule": "drop"}"#),
+    ("See also", r#"{"rule": "drop"}"#),
+    ("Normdaten", r#"{"rule": "authority_control"}"#),
+    ("典拠管理", r#"{"rule": "alias", "target": "Normdaten"}"#),
+    (
+        "Authority control",
+        r#"{"rule": "alias", "target": "Normdaten"}"#,
+    ),
+    ("Coord", r#"{"rule": "coordinates"}"#),
+    ("ウィキ座標", r#"{"rule": "alias", "target": "Coord"}"#),
+    ("年月日", r#"{"rule": "date"}"#),
+    ("Start date", r#"{"rule": "date", "role": "start"}"#),
+    ("End date", r#"{"rule": "date", "role": "end"}"#),
+    ("Birth date", r#"{"rule": "date", "role": "birth"}"#),
+    ("Death date", r#"{"rule": "date", "role": "death"}"#),
+    (
+        "生年月日と年齢",
+        r#"{"rule": "date", "role": "birth", "age": true}"#,
+    ),
+    (
+        "Birth date and age",
+        r#"{"rule": "alias", "target": "生年月日と年齢"}"#,
+    ),
+    ("没年齢", r#"{"rule": "death_age"}"#),
+    (
+        "死亡年月日と没年齢",
+        r#"{"rule": "death_age", "date": true}"#,
+    ),
+    (
+        "Death date and age",
+        r#"{"rule": "death_age", "death_first": true, "date": true}"#,
+    ),
+    (
+        "ウィキ座標度分秒",
+        r#"{"rule": "alias", "target": "Coord"}"#,
+    ),
+];
+
+impl Default for TemplateRules {
+    fn default() -> Self {
+        let mut rules = TemplateRules {
+            rules: HashMap::new(),
+        };
+        for (name, rule) in DEFAULT_RULES {
+            rules.insert(name, serde_json::from_str(rule).unwrap());
+        }
+        rules
+    }
+}
+
+impl TemplateRules {
+    // Add or override rules with a JSON file such as
+    // {"要出典": {"rule": "marker", "text": "[要出典]"}, "Quote": {"rule": "named", "name": "text"}}
+    pub fn load(&mut self, path: &str) {
+        let file =
+            File::open(path).unwrap_or_else(|_| panic!("can't open template rule file[{}]", path));
+        let rules: HashMap<String, TemplateRule> = serde_json::from_reader(BufReader::new(file))
+            .unwrap_or_else(|e| panic!("can't parse template rule file[{}]. {}", path, e));
+        for (name, rule) in rules {
+            self.insert(name.as_str(), rule);
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, rule: TemplateRule) {
+        self.rules.insert(normalize_template_name(name), rule);
+    }
+
+    // Aliases are followed. Infobox and citation templates without a rule are
+    // infoboxes and citations.
+    pub fn get(&self, name: &str) -> Option<&TemplateRule> {
+        let mut name = normalize_template_name(name);
+        // a limit for alias loops
+        for _ in 0..10 {
+            match self.rules.get(&name) {
+                Some(TemplateRule::Alias { target }) => name = normalize_template_name(target),
+                Some(rule) => return Some(rule),
+                None if is_infobox(name.as_str()) => return Some(&TemplateRule::Infobox),
+                None if is_citation(name.as_str()) => return Some(&TemplateRule::Citation),
+                None => return None,
+            }
+        }
+        debug!("[WARN] Template alias loop... [{}]", name);
+        None
+    }
+}
+
+// What templates are rendered with, passed to the parsers for each page
+pub struct TemplateContext<'a> {
+    pub rules: &'a TemplateRules,
+    // the date ages of {{生年月日と年齢}} are counted to
+    pub today: Option<DateTime>,
+}
+
+// Template names as MediaWiki compares them: underscores are spaces,
+// the namespace is optional and the first letter is case insensitive.
+pub fn normalize_template_name(name: &str) -> String {
+    let name = name.replace('_', " ");
+    let name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let name = match name.split_once(':') {
+        Some((namespace, rest))
+            if namespace.eq_ignore_ascii_case("template") || namespace == "テンプレート" =>
+        {
+            rest.trim()
         }
+        _ => name.as_str(),
     };
-    return template_type;
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
 }
 
-pub fn parse_template(name: &Vec<Node>, parameters: &Vec<Parameter>) -> Option<String> {
-    return match parse_template_type(name) {
-        TemplateType::Lang => Some(get_lang_template_text(&parameters)),
-        TemplateType::TemporaryLink => Some(get_temporary_link_template_text(&parameters)),
-        TemplateType::Unicode => Some(get_temporary_link_template_text(&parameters)),
-        TemplateType::Otheruses => None,
-        TemplateType::Redirect => None,
-        TemplateType::Unknown => {
+pub fn parse_template_rule<'a>(
+    rules: &'a TemplateRules,
+    nodes: &[Node],
+) -> Option<&'a TemplateRule> {
+    let name = parse_text_only(nodes);
+    let rule = rules.get(name.as_str());
+    if rule.is_none() {
+        debug!("[WARN] Unknown Template Type... [{}]", name);
+    }
+    rule
+}
+
+pub fn parse_template(
+    context: &TemplateContext,
+    name: &[Node],
+    parameters: &[Parameter],
+) -> Option<String> {
+    let rule = match parse_template_rule(context.rules, name) {
+        Some(rule) => rule,
+        None => {
             trace!("Params: [{:?}]", parameters);
-            None
+            return None;
         }
     };
+    match rule {
+        TemplateRule::Drop
+        | TemplateRule::Infobox
+        | TemplateRule::Citation
+        | TemplateRule::AuthorityControl
+        | TemplateRule::Coordinates
+        | TemplateRule::Alias { .. } => None,
+        TemplateRule::Param { index } => Some(get_param(parameters, *index).unwrap_or_default()),
+        TemplateRule::Named { name } => Some(get_named_param(parameters, name).unwrap_or_default()),
+        TemplateRule::Join { params, separator } => {
+            let values: Vec<String> = if params.is_empty() {
+                (1..=positional_count(parameters))
+                    .filter_map(|index| get_param(parameters, index))
+                    .collect()
+            } else {
+                params
+                    .iter()
+                    .filter_map(|name| get_named_param(parameters, name))
+                    .collect()
+            };
+            Some(
+                values
+                    .into_iter()
+                    .filter(|value| !value.is_empty())
+                    .collect::<Vec<String>>()
+                    .join(separator),
+            )
+        }
+        TemplateRule::Wrap {
+            param,
+            prefix,
+            suffix,
+        } => Some(match get_named_param(parameters, param) {
+            Some(value) if !value.is_empty() => format!("{}{}{}", prefix, value, suffix),
+            _ => String::new(),
+        }),
+        TemplateRule::Marker { text } => Some(match text {
+            Some(text) => text.to_string(),
+            None => format!("{{{{{}}}}}", parse_text_only(name).trim()),
+        }),
+        TemplateRule::Lang => Some(get_lang_template_text(parameters)),
+        TemplateRule::Ruby { parentheses } => {
+            let text = get_param(parameters, 1).unwrap_or_default();
+            Some(match get_param(parameters, 2) {
+                Some(reading) if *parentheses && !reading.is_empty() => {
+                    format!("{}（{}）", text, reading)
+                }
+                _ => text,
+            })
+        }
+        TemplateRule::Date { age, .. } => {
+            Some(render_date(parameters, *age, context.today.as_ref()).unwrap_or_default())
+        }
+        TemplateRule::DeathAge { death_first, date } => {
+            Some(render_death_age(parameters, *death_first, *date).unwrap_or_default())
+        }
+    }
+}
+
+// text of the positional parameter, `|1=value` is also positional
+pub fn get_param(parameters: &[Parameter], index: usize) -> Option<String> {
+    let mut position = 0;
+    for parameter in parameters {
+        match &parameter.name {
+            Some(name) if parse_text_only(name).trim() == index.to_string() => {
+                return Some(parse_text_only(&parameter.value).trim().to_string());
+            }
+            Some(_) => {}
+            None => {
+                position += 1;
+                if position == index {
+                    return Some(parse_text_only(&parameter.value).trim().to_string());
+                }
+            }
+        }
+    }
+    None
 }
 
-pub fn get_lang_template_text(nodes: &Vec<Parameter>) -> String {
+// a name of digits is a positional parameter
+fn get_named_param(parameters: &[Parameter], name: &str) -> Option<String> {
+    if let Ok(index) = name.parse() {
+        return get_param(parameters, index);
+    }
+    parameters
+        .iter()
+        .find(|p| {
+            p.name
+                .as_ref()
+                .map(|n| parse_text_only(n).trim() == name)
+                .unwrap_or(false)
+        })
+        .map(|p| parse_text_only(&p.value).trim().to_string())
+}
+
+fn positional_count(parameters: &[Parameter]) -> usize {
+    parameters.iter().filter(|p| p.name.is_none()).count()
+}
+
+pub fn get_lang_template_text(nodes: &[Parameter]) -> String {
     let len = nodes.len();
     let str = if len >= 2 {
         parse_text_only(&nodes.get(1).unwrap().value)
@@ -56,12 +369,99 @@ pub fn get_lang_template_text(nodes: &Vec<Parameter>) -> String {
     return str;
 }
 
-pub fn get_temporary_link_template_text(nodes: &Vec<Parameter>) -> String {
-    let str = if nodes.len() > 0 {
-        parse_text_only(&nodes.get(0).unwrap().value)
-    } else {
-        debug!("[WARN]Template param is 0. Unexpected value... {:?}", nodes);
-        String::new()
-    };
-    return str;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_names() {
+        assert_eq!(normalize_template_name("lang-en"), "Lang-en");
+        assert_eq!(normalize_template_name("See_also"), "See also");
+        assert_eq!(normalize_template_name(" See  also "), "See also");
+        assert_eq!(normalize_template_name("Template:main"), "Main");
+        assert_eq!(normalize_template_name("template: Main"), "Main");
+        assert_eq!(normalize_template_name("テンプレート:要出典"), "要出典");
+        assert_eq!(normalize_template_name(""), "");
+    }
+
+    #[test]
+    fn default_rules() {
+        let rules = TemplateRules::default();
+        assert!(matches!(rules.get("lang-en"), Some(TemplateRule::Lang)));
+        assert!(matches!(
+            rules.get("Template:see_also"),
+            Some(TemplateRule::Drop)
+        ));
+        assert!(matches!(
+            rules.get("Infobox 人物"),
+            Some(TemplateRule::Infobox)
+        ));
+        assert!(matches!(
+            rules.get("Cite web"),
+            Some(TemplateRule::Citation)
+        ));
+        assert!(rules.get("存在しない").is_none());
+    }
+
+    #[test]
+    fn aliases() {
+        let rules = TemplateRules::default();
+        assert!(matches!(
+            rules.get("典拠管理"),
+            Some(TemplateRule::AuthorityControl)
+        ));
+        assert!(matches!(
+            rules.get("読み仮名_ruby不使用"),
+            Some(TemplateRule::Ruby { parentheses: true })
+        ));
+    }
+
+    #[test]
+    fn alias_loop() {
+        let mut rules = TemplateRules::default();
+        let alias = |target: &str| TemplateRule::Alias {
+            target: target.to_string(),
+        };
+        rules.insert("A", alias("B"));
+        rules.insert("B", alias("Template:a"));
+        assert!(rules.get("A").is_none());
+        // 9 aliases are followed
+        for index in 0..9 {
+            rules.insert(&format!("C{}", index), alias(&format!("C{}", index + 1)));
+        }
+        rules.insert("C9", TemplateRule::Drop);
+        assert!(matches!(rules.get("C0"), Some(TemplateRule::Drop)));
+        assert!(matches!(rules.get("C1"), Some(TemplateRule::Drop)));
+        rules.insert("C", alias("C0"));
+        assert!(rules.get("C").is_none());
+    }
+
+    #[test]
+    fn load_rules() {
+        let path = std::env::temp_dir().join(format!("template_rules_{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"要出典": {"rule": "marker", "text": "[要出典]"},
+                "Template:quote": {"rule": "named", "name": "text"},
+                "Lang": {"rule": "alias", "target": "quote"}}"#,
+        )
+        .unwrap();
+        let mut rules = TemplateRules::default();
+        rules.load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            rules.get("要出典"),
+            Some(TemplateRule::Marker { text: Some(text) }) if text == "[要出典]"
+        ));
+        assert!(matches!(
+            rules.get("Quote"),
+            Some(TemplateRule::Named { name }) if name == "text"
+        ));
+        assert!(matches!(
+            rules.get("lang"),
+            Some(TemplateRule::Named { .. })
+        ));
+        // the other default rules are kept
+        assert!(matches!(rules.get("lang-en"), Some(TemplateRule::Lang)));
+    }
 }